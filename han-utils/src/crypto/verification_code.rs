@@ -1,11 +1,13 @@
 use rand::Rng;
 
-// max 15
+// max 15, every digit drawn on its own so all codes are equally likely
 pub fn gen_number_verification_code(length: usize) -> String {
     assert!(length < 15);
 
     let mut rng = rand::thread_rng();
-    format!("{:06}", rng.gen::<u32>())[0..length].to_string()
+    (0..length)
+        .map(|_| char::from(b'0' + rng.gen_range(0..10u8)))
+        .collect()
 }
 
 #[test]
@@ -13,5 +15,7 @@ fn test_gen_verification_code() {
     for _ in 0..10 {
         let code = gen_number_verification_code(6);
         println!("{}", code);
+        assert_eq!(code.len(), 6);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
     }
 }
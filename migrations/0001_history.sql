@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS history (
+    id                      INTEGER PRIMARY KEY AUTOINCREMENT,
+    name                    TEXT NOT NULL,
+    timestamp               INTEGER NOT NULL,
+    pledge                  REAL NOT NULL,
+    power                   REAL NOT NULL,
+    blocks                  INTEGER NOT NULL,
+    rewards                 REAL NOT NULL
+);
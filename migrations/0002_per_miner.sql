@@ -0,0 +1,13 @@
+-- rows written before per miner history keep miner = 'all'
+ALTER TABLE history ADD COLUMN miner TEXT NOT NULL DEFAULT 'all';
+
+CREATE INDEX IF NOT EXISTS history_name_miner_timestamp
+ON history (name, miner, timestamp);
+
+-- the aggregate over all miners of each sample
+CREATE VIEW IF NOT EXISTS history_total AS
+SELECT MIN(id) AS id, name, 'all' AS miner, timestamp,
+    SUM(pledge) AS pledge, SUM(power) AS power,
+    SUM(blocks) AS blocks, SUM(rewards) AS rewards
+FROM history
+GROUP BY name, timestamp;
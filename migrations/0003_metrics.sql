@@ -0,0 +1,30 @@
+-- everything else filfox reports per miner, balances in FIL and power in TiB
+ALTER TABLE history ADD COLUMN raw_power REAL NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN balance REAL NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN available_balance REAL NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN vesting_funds REAL NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN pre_commit_deposits REAL NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN sectors_active INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN sectors_faulty INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN sectors_live INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN sectors_recovering INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN power_rank INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE history ADD COLUMN raw_power_rank INTEGER NOT NULL DEFAULT 0;
+
+-- ranks do not add up, the aggregate leaves them at 0
+DROP VIEW IF EXISTS history_total;
+CREATE VIEW history_total AS
+SELECT MIN(id) AS id, name, 'all' AS miner, timestamp,
+    SUM(pledge) AS pledge, SUM(power) AS power,
+    SUM(blocks) AS blocks, SUM(rewards) AS rewards,
+    SUM(raw_power) AS raw_power, SUM(balance) AS balance,
+    SUM(available_balance) AS available_balance,
+    SUM(vesting_funds) AS vesting_funds,
+    SUM(pre_commit_deposits) AS pre_commit_deposits,
+    SUM(sectors_active) AS sectors_active,
+    SUM(sectors_faulty) AS sectors_faulty,
+    SUM(sectors_live) AS sectors_live,
+    SUM(sectors_recovering) AS sectors_recovering,
+    0 AS power_rank, 0 AS raw_power_rank
+FROM history
+GROUP BY name, timestamp;
@@ -0,0 +1,87 @@
+-- rollups written by compaction, one row per miner and bucket start
+CREATE TABLE IF NOT EXISTS history_hourly (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    miner TEXT NOT NULL DEFAULT 'all',
+    timestamp INTEGER NOT NULL,
+    pledge REAL NOT NULL DEFAULT 0,
+    power REAL NOT NULL DEFAULT 0,
+    blocks INTEGER NOT NULL DEFAULT 0,
+    rewards REAL NOT NULL DEFAULT 0,
+    raw_power REAL NOT NULL DEFAULT 0,
+    balance REAL NOT NULL DEFAULT 0,
+    available_balance REAL NOT NULL DEFAULT 0,
+    vesting_funds REAL NOT NULL DEFAULT 0,
+    pre_commit_deposits REAL NOT NULL DEFAULT 0,
+    sectors_active INTEGER NOT NULL DEFAULT 0,
+    sectors_faulty INTEGER NOT NULL DEFAULT 0,
+    sectors_live INTEGER NOT NULL DEFAULT 0,
+    sectors_recovering INTEGER NOT NULL DEFAULT 0,
+    power_rank INTEGER NOT NULL DEFAULT 0,
+    raw_power_rank INTEGER NOT NULL DEFAULT 0
+);
+CREATE UNIQUE INDEX IF NOT EXISTS history_hourly_name_miner_timestamp
+    ON history_hourly (name, miner, timestamp);
+
+CREATE TABLE IF NOT EXISTS history_daily (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    miner TEXT NOT NULL DEFAULT 'all',
+    timestamp INTEGER NOT NULL,
+    pledge REAL NOT NULL DEFAULT 0,
+    power REAL NOT NULL DEFAULT 0,
+    blocks INTEGER NOT NULL DEFAULT 0,
+    rewards REAL NOT NULL DEFAULT 0,
+    raw_power REAL NOT NULL DEFAULT 0,
+    balance REAL NOT NULL DEFAULT 0,
+    available_balance REAL NOT NULL DEFAULT 0,
+    vesting_funds REAL NOT NULL DEFAULT 0,
+    pre_commit_deposits REAL NOT NULL DEFAULT 0,
+    sectors_active INTEGER NOT NULL DEFAULT 0,
+    sectors_faulty INTEGER NOT NULL DEFAULT 0,
+    sectors_live INTEGER NOT NULL DEFAULT 0,
+    sectors_recovering INTEGER NOT NULL DEFAULT 0,
+    power_rank INTEGER NOT NULL DEFAULT 0,
+    raw_power_rank INTEGER NOT NULL DEFAULT 0
+);
+CREATE UNIQUE INDEX IF NOT EXISTS history_daily_name_miner_timestamp
+    ON history_daily (name, miner, timestamp);
+
+-- compaction keeps the tiers disjoint in time, so reads see one series
+-- that gets coarser further back
+DROP VIEW IF EXISTS history_tiered;
+CREATE VIEW history_tiered AS
+SELECT id, name, miner, timestamp, pledge, power, blocks, rewards,
+    raw_power, balance, available_balance, vesting_funds, pre_commit_deposits,
+    sectors_active, sectors_faulty, sectors_live, sectors_recovering,
+    power_rank, raw_power_rank
+FROM history
+UNION ALL
+SELECT id, name, miner, timestamp, pledge, power, blocks, rewards,
+    raw_power, balance, available_balance, vesting_funds, pre_commit_deposits,
+    sectors_active, sectors_faulty, sectors_live, sectors_recovering,
+    power_rank, raw_power_rank
+FROM history_hourly
+UNION ALL
+SELECT id, name, miner, timestamp, pledge, power, blocks, rewards,
+    raw_power, balance, available_balance, vesting_funds, pre_commit_deposits,
+    sectors_active, sectors_faulty, sectors_live, sectors_recovering,
+    power_rank, raw_power_rank
+FROM history_daily;
+
+DROP VIEW IF EXISTS history_total;
+CREATE VIEW history_total AS
+SELECT MIN(id) AS id, name, 'all' AS miner, timestamp,
+    SUM(pledge) AS pledge, SUM(power) AS power,
+    SUM(blocks) AS blocks, SUM(rewards) AS rewards,
+    SUM(raw_power) AS raw_power, SUM(balance) AS balance,
+    SUM(available_balance) AS available_balance,
+    SUM(vesting_funds) AS vesting_funds,
+    SUM(pre_commit_deposits) AS pre_commit_deposits,
+    SUM(sectors_active) AS sectors_active,
+    SUM(sectors_faulty) AS sectors_faulty,
+    SUM(sectors_live) AS sectors_live,
+    SUM(sectors_recovering) AS sectors_recovering,
+    0 AS power_rank, 0 AS raw_power_rank
+FROM history_tiered
+GROUP BY name, timestamp;
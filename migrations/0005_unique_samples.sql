@@ -0,0 +1,7 @@
+-- one sample per miner and time, imports and retries rely on it to skip duplicates
+DELETE FROM history WHERE id NOT IN (
+    SELECT MIN(id) FROM history GROUP BY name, miner, timestamp
+);
+DROP INDEX IF EXISTS history_name_miner_timestamp;
+CREATE UNIQUE INDEX IF NOT EXISTS history_name_miner_timestamp
+ON history (name, miner, timestamp);
@@ -0,0 +1,112 @@
+-- the sqlite schema up to 0004_retention in one step
+-- timestamp is part of the key so `history` can become a timescale hypertable
+CREATE TABLE IF NOT EXISTS history (
+    id BIGSERIAL NOT NULL,
+    name TEXT NOT NULL,
+    miner TEXT NOT NULL DEFAULT 'all',
+    timestamp BIGINT NOT NULL,
+    pledge DOUBLE PRECISION NOT NULL DEFAULT 0,
+    power DOUBLE PRECISION NOT NULL DEFAULT 0,
+    blocks BIGINT NOT NULL DEFAULT 0,
+    rewards DOUBLE PRECISION NOT NULL DEFAULT 0,
+    raw_power DOUBLE PRECISION NOT NULL DEFAULT 0,
+    balance DOUBLE PRECISION NOT NULL DEFAULT 0,
+    available_balance DOUBLE PRECISION NOT NULL DEFAULT 0,
+    vesting_funds DOUBLE PRECISION NOT NULL DEFAULT 0,
+    pre_commit_deposits DOUBLE PRECISION NOT NULL DEFAULT 0,
+    sectors_active BIGINT NOT NULL DEFAULT 0,
+    sectors_faulty BIGINT NOT NULL DEFAULT 0,
+    sectors_live BIGINT NOT NULL DEFAULT 0,
+    sectors_recovering BIGINT NOT NULL DEFAULT 0,
+    power_rank BIGINT NOT NULL DEFAULT 0,
+    raw_power_rank BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (id, timestamp)
+);
+CREATE INDEX IF NOT EXISTS history_name_miner_timestamp
+    ON history (name, miner, timestamp);
+
+CREATE TABLE IF NOT EXISTS history_hourly (
+    id BIGSERIAL NOT NULL,
+    name TEXT NOT NULL,
+    miner TEXT NOT NULL DEFAULT 'all',
+    timestamp BIGINT NOT NULL,
+    pledge DOUBLE PRECISION NOT NULL DEFAULT 0,
+    power DOUBLE PRECISION NOT NULL DEFAULT 0,
+    blocks BIGINT NOT NULL DEFAULT 0,
+    rewards DOUBLE PRECISION NOT NULL DEFAULT 0,
+    raw_power DOUBLE PRECISION NOT NULL DEFAULT 0,
+    balance DOUBLE PRECISION NOT NULL DEFAULT 0,
+    available_balance DOUBLE PRECISION NOT NULL DEFAULT 0,
+    vesting_funds DOUBLE PRECISION NOT NULL DEFAULT 0,
+    pre_commit_deposits DOUBLE PRECISION NOT NULL DEFAULT 0,
+    sectors_active BIGINT NOT NULL DEFAULT 0,
+    sectors_faulty BIGINT NOT NULL DEFAULT 0,
+    sectors_live BIGINT NOT NULL DEFAULT 0,
+    sectors_recovering BIGINT NOT NULL DEFAULT 0,
+    power_rank BIGINT NOT NULL DEFAULT 0,
+    raw_power_rank BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (id)
+);
+CREATE UNIQUE INDEX IF NOT EXISTS history_hourly_name_miner_timestamp
+    ON history_hourly (name, miner, timestamp);
+
+CREATE TABLE IF NOT EXISTS history_daily (
+    id BIGSERIAL NOT NULL,
+    name TEXT NOT NULL,
+    miner TEXT NOT NULL DEFAULT 'all',
+    timestamp BIGINT NOT NULL,
+    pledge DOUBLE PRECISION NOT NULL DEFAULT 0,
+    power DOUBLE PRECISION NOT NULL DEFAULT 0,
+    blocks BIGINT NOT NULL DEFAULT 0,
+    rewards DOUBLE PRECISION NOT NULL DEFAULT 0,
+    raw_power DOUBLE PRECISION NOT NULL DEFAULT 0,
+    balance DOUBLE PRECISION NOT NULL DEFAULT 0,
+    available_balance DOUBLE PRECISION NOT NULL DEFAULT 0,
+    vesting_funds DOUBLE PRECISION NOT NULL DEFAULT 0,
+    pre_commit_deposits DOUBLE PRECISION NOT NULL DEFAULT 0,
+    sectors_active BIGINT NOT NULL DEFAULT 0,
+    sectors_faulty BIGINT NOT NULL DEFAULT 0,
+    sectors_live BIGINT NOT NULL DEFAULT 0,
+    sectors_recovering BIGINT NOT NULL DEFAULT 0,
+    power_rank BIGINT NOT NULL DEFAULT 0,
+    raw_power_rank BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (id)
+);
+CREATE UNIQUE INDEX IF NOT EXISTS history_daily_name_miner_timestamp
+    ON history_daily (name, miner, timestamp);
+
+CREATE OR REPLACE VIEW history_tiered AS
+SELECT id, name, miner, timestamp, pledge, power, blocks, rewards,
+    raw_power, balance, available_balance, vesting_funds, pre_commit_deposits,
+    sectors_active, sectors_faulty, sectors_live, sectors_recovering,
+    power_rank, raw_power_rank
+FROM history
+UNION ALL
+SELECT id, name, miner, timestamp, pledge, power, blocks, rewards,
+    raw_power, balance, available_balance, vesting_funds, pre_commit_deposits,
+    sectors_active, sectors_faulty, sectors_live, sectors_recovering,
+    power_rank, raw_power_rank
+FROM history_hourly
+UNION ALL
+SELECT id, name, miner, timestamp, pledge, power, blocks, rewards,
+    raw_power, balance, available_balance, vesting_funds, pre_commit_deposits,
+    sectors_active, sectors_faulty, sectors_live, sectors_recovering,
+    power_rank, raw_power_rank
+FROM history_daily;
+
+-- sums of BIGINT are NUMERIC in postgres, cast back so rows decode the same
+CREATE OR REPLACE VIEW history_total AS
+SELECT MIN(id) AS id, name, 'all'::TEXT AS miner, timestamp,
+    SUM(pledge) AS pledge, SUM(power) AS power,
+    SUM(blocks)::BIGINT AS blocks, SUM(rewards) AS rewards,
+    SUM(raw_power) AS raw_power, SUM(balance) AS balance,
+    SUM(available_balance) AS available_balance,
+    SUM(vesting_funds) AS vesting_funds,
+    SUM(pre_commit_deposits) AS pre_commit_deposits,
+    SUM(sectors_active)::BIGINT AS sectors_active,
+    SUM(sectors_faulty)::BIGINT AS sectors_faulty,
+    SUM(sectors_live)::BIGINT AS sectors_live,
+    SUM(sectors_recovering)::BIGINT AS sectors_recovering,
+    0::BIGINT AS power_rank, 0::BIGINT AS raw_power_rank
+FROM history_tiered
+GROUP BY name, timestamp;
@@ -0,0 +1,6 @@
+-- one sample per miner and time, imports and retries rely on it to skip duplicates
+DELETE FROM history a USING history b
+WHERE a.name = b.name AND a.miner = b.miner AND a.timestamp = b.timestamp AND a.id > b.id;
+DROP INDEX IF EXISTS history_name_miner_timestamp;
+CREATE UNIQUE INDEX IF NOT EXISTS history_name_miner_timestamp
+    ON history (name, miner, timestamp);
@@ -0,0 +1,122 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::data::{
+    history::subscribe::{HistoryItem, GLOBAL_HISTORY},
+    keys::{ApiKey, Scope, GLOBAL_KEYS},
+    login::{Session, GLOBAL_LOGIN},
+    users::GLOBAL_USERS,
+};
+
+use super::*;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// who a request was made by, handlers take it as `Extension<Caller>`
+#[derive(Debug, Clone)]
+pub struct Caller {
+    // the api key or session, for messages
+    pub key: String,
+    // scoped to this user's watchlist and subscriptions when set
+    pub user: Option<String>,
+}
+
+impl From<&ApiKey> for Caller {
+    fn from(k: &ApiKey) -> Self {
+        Self {
+            key: format!("api key {}", k.id),
+            user: k.user.clone(),
+        }
+    }
+}
+
+impl From<&Session> for Caller {
+    fn from(s: &Session) -> Self {
+        Self {
+            key: format!("session {}", s.id),
+            user: Some(s.user.clone()),
+        }
+    }
+}
+
+impl Caller {
+    // the nodes this caller sees, all of them when it is no user
+    pub async fn nodes(&self) -> Option<Vec<String>> {
+        match &self.user {
+            Some(user) => Some(GLOBAL_USERS.watchlist(user).await),
+            None => None,
+        }
+    }
+
+    pub fn owns(&self, item: &HistoryItem) -> bool {
+        self.user.is_none() || item.owner == self.user
+    }
+
+    // the history subscriptions this caller sees
+    pub async fn histories(&self) -> Vec<HistoryItem> {
+        let mut items = GLOBAL_HISTORY.get().await;
+        items.retain(|i| self.owns(i));
+        items
+    }
+
+    // subscription `name`, if this caller sees it
+    pub async fn history(&self, name: &str) -> anyhow::Result<HistoryItem> {
+        match GLOBAL_HISTORY.get_history(name.to_string()).await {
+            Ok(item) if self.owns(&item) => Ok(item),
+            // others' subscriptions are not found, not forbidden
+            _ => Err(anyhow::anyhow!("history item not found!")),
+        }
+    }
+}
+
+// `X-Api-Key: <key>`, or `Authorization: Bearer <key>`, keys or session tokens
+pub(crate) fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    if let Some(key) = headers.get(API_KEY_HEADER) {
+        return key.to_str().ok();
+    }
+    let auth = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    auth.strip_prefix("Bearer ").map(str::trim)
+}
+
+// .route_layer(middleware::from_fn_with_state(Scope::Read, apis::auth::require_scope))
+// turn away requests without a key or session holding `scope`
+pub async fn require_scope(
+    State(scope): State<Scope>,
+    mut req: Request<Body>,
+    next: Next<Body>,
+) -> core::result::Result<Response, Res<String>> {
+    let key = match presented_key(req.headers()) {
+        Some(key) => key,
+        None => {
+            return Err(Res::custom_fail(
+                StatusCode::UNAUTHORIZED,
+                "missing api key, send it in X-Api-Key".to_string(),
+            ))
+        }
+    };
+    let (caller, allowed) = match GLOBAL_KEYS.find(key).await {
+        Some(k) => (Caller::from(&k), k.allows(scope)),
+        None => match GLOBAL_LOGIN.session(key).await {
+            Some(s) => (Caller::from(&s), s.allows(scope)),
+            None => {
+                return Err(Res::custom_fail(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid api key".to_string(),
+                ))
+            }
+        },
+    };
+    if !allowed {
+        return Err(Res::custom_fail(
+            StatusCode::FORBIDDEN,
+            format!("{} lacks the {} scope", caller.key, scope),
+        ));
+    }
+    req.extensions_mut().insert(caller);
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,55 @@
+use std::io::Write;
+
+use axum::body::{Bytes, StreamBody};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+// bytes sent to the client at once
+const CHUNK_SIZE: usize = 64 * 1024;
+// chunks buffered for a slow client
+const CHUNK_BUFFER: usize = 16;
+
+pub(crate) type BodyChunk = Result<Bytes, std::io::Error>;
+pub(crate) type ChannelBody = StreamBody<ReceiverStream<BodyChunk>>;
+
+// hands what a blocking writer writes to a response body in `CHUNK_SIZE` pieces
+pub(crate) struct BodyWriter {
+    tx: mpsc::Sender<BodyChunk>,
+    buf: Vec<u8>,
+}
+
+impl BodyWriter {
+    // the writer, a sender to abort the body with an error, and the body
+    pub(crate) fn channel() -> (BodyWriter, mpsc::Sender<BodyChunk>, ChannelBody) {
+        let (tx, rx) = mpsc::channel(CHUNK_BUFFER);
+        let writer = BodyWriter {
+            tx: tx.clone(),
+            buf: Vec::with_capacity(CHUNK_SIZE),
+        };
+        (writer, tx, StreamBody::new(ReceiverStream::new(rx)))
+    }
+
+    fn send(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::take(&mut self.buf));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client went away"))
+    }
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send()
+    }
+}
@@ -0,0 +1,33 @@
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use han_utils::res::Res;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apis::auth::Caller,
+    data::source::breaker::{CircuitBreaker, GLOBAL_BREAKERS},
+};
+
+pub mod reset;
+
+pub async fn get_breakers(
+    Extension(caller): Extension<Caller>,
+) -> core::result::Result<Res<Vec<CircuitBreaker>>, Res<String>> {
+    match get_breakers_handler(&caller).await {
+        Ok(d) => Ok(Res::success(d)),
+        Err(e) => Err(Res::custom_fail(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+// a user only sees the breakers of the nodes on their watchlist
+pub async fn get_breakers_handler(caller: &Caller) -> anyhow::Result<Vec<CircuitBreaker>> {
+    let mut breakers = GLOBAL_BREAKERS.get().await;
+    if let Some(nodes) = caller.nodes().await {
+        breakers.retain(|b| nodes.contains(&b.node));
+    }
+
+    Ok(breakers)
+}
@@ -0,0 +1,26 @@
+use super::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BreakersResetReq {
+    pub ids: Vec<String>,
+}
+
+pub async fn post_breakers_reset(
+    Json(req): Json<BreakersResetReq>,
+) -> core::result::Result<Res<Vec<CircuitBreaker>>, Res<String>> {
+    match post_breakers_reset_handler(req).await {
+        Ok(d) => Ok(Res::success(d)),
+        Err(e) => Err(Res::custom_fail(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+pub async fn post_breakers_reset_handler(
+    req: BreakersResetReq,
+) -> anyhow::Result<Vec<CircuitBreaker>> {
+    GLOBAL_BREAKERS.reset(&req.ids).await;
+
+    Ok(GLOBAL_BREAKERS.get().await)
+}
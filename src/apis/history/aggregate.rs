@@ -0,0 +1,81 @@
+use crate::{
+    apis::auth::Caller,
+    data::{
+        filfox::models::MinerInfo,
+        history::{
+            aggregate::{Aggregate, Bucket},
+            store::SharedStore,
+        },
+    },
+};
+
+use super::super::*;
+use axum::Extension;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryAggregateReq {
+    pub name: String,
+    pub from: i64,
+    pub to: i64,
+    pub miner: Option<String>,
+    pub miners: Option<Vec<String>>,
+    pub bucket: Bucket,
+    pub aggregate: Aggregate,
+}
+
+impl HistoryAggregateReq {
+    pub fn miners(&self) -> Vec<String> {
+        super::miners(self.miners.clone().unwrap_or_default(), self.miner.clone())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryAggregateRes {
+    pub name: String,
+    pub bucket: Bucket,
+    pub aggregate: Aggregate,
+    // start of each bucket
+    pub time: Vec<i64>,
+    pub info: Vec<MinerInfo>,
+}
+
+pub async fn post_history_aggregate(
+    Extension(store): Extension<SharedStore>,
+    Extension(caller): Extension<Caller>,
+    Json(req): Json<HistoryAggregateReq>,
+) -> core::result::Result<Res<HistoryAggregateRes>, Res<String>> {
+    match post_history_aggregate_handler(&caller, req, store).await {
+        Ok(d) => Ok(Res::success(d)),
+        Err(e) => Err(Res::custom_fail(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+pub async fn post_history_aggregate_handler(
+    caller: &Caller,
+    req: HistoryAggregateReq,
+    store: SharedStore,
+) -> anyhow::Result<HistoryAggregateRes> {
+    tracing::info!("{:?}", &req);
+    caller.history(&req.name).await?;
+    let (time_vec, info_vec) = store
+        .aggregate(
+            req.name.clone(),
+            req.miners(),
+            req.from,
+            req.to,
+            req.bucket,
+            req.aggregate,
+        )
+        .await?;
+
+    Ok(HistoryAggregateRes {
+        name: req.name,
+        bucket: req.bucket,
+        aggregate: req.aggregate,
+        time: time_vec,
+        info: info_vec,
+    })
+}
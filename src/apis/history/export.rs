@@ -0,0 +1,101 @@
+use axum::{
+    extract::Query,
+    http::header,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::Utc;
+
+use crate::{
+    apis::{auth::Caller, body::BodyWriter},
+    data::history::{
+        export::{export, ExportFormat, ExportQuery},
+        store::SharedStore,
+    },
+};
+
+use super::super::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryExportReq {
+    pub name: String,
+    // defaults to everything up to now
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub miner: Option<String>,
+    // comma separated, all miners when neither is given
+    pub miners: Option<String>,
+    // csv when not given
+    pub format: Option<ExportFormat>,
+}
+
+impl HistoryExportReq {
+    pub fn query(self) -> ExportQuery {
+        let miners = self
+            .miners
+            .unwrap_or_default()
+            .split(',')
+            .map(|m| m.trim().to_string())
+            .filter(|m| !m.is_empty())
+            .collect();
+        let miners = super::miners(miners, self.miner);
+
+        ExportQuery {
+            name: self.name,
+            miners,
+            from: self.from.unwrap_or(0),
+            to: self.to.unwrap_or_else(|| Utc::now().timestamp() + 1),
+            format: self.format.unwrap_or(ExportFormat::Csv),
+        }
+    }
+}
+
+// .on(MethodFilter::GET, apis::history::export::get_history_export)
+pub async fn get_history_export(
+    Extension(store): Extension<SharedStore>,
+    Extension(caller): Extension<Caller>,
+    Query(req): Query<HistoryExportReq>,
+) -> core::result::Result<Response, Res<String>> {
+    tracing::info!("{:?}", &req);
+    if let Err(e) = caller.history(&req.name).await {
+        return Err(Res::custom_fail(StatusCode::NOT_FOUND, e.to_string()));
+    }
+    let query = req.query();
+    if query.format == ExportFormat::Parquet && !cfg!(feature = "parquet") {
+        return Err(Res::custom_fail(
+            StatusCode::BAD_REQUEST,
+            "this build has no parquet support".to_string(),
+        ));
+    }
+
+    let filename: String = query
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let headers = [
+        (
+            header::CONTENT_TYPE,
+            query.format.content_type().to_string(),
+        ),
+        (
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.{}\"",
+                filename,
+                query.format.extension()
+            ),
+        ),
+    ];
+
+    let (writer, tx, body) = BodyWriter::channel();
+    tokio::spawn(async move {
+        if let Err(e) = export(store, query, writer).await {
+            tracing::error!("export history error: {}", e);
+            // abort the body so a cut file is not taken for a complete one
+            let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+        }
+    });
+
+    Ok((headers, body).into_response())
+}
@@ -1,7 +1,7 @@
-use crate::data::history::subscribe::{HistoryItem, GLOBAL_HISTORY};
+use crate::{apis::auth::Caller, data::history::subscribe::HistoryItem};
 
 use super::super::*;
-use axum::extract::Query;
+use axum::{extract::Query, Extension};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HistoryGetReq {
@@ -9,9 +9,10 @@ pub struct HistoryGetReq {
 }
 
 pub async fn get_history(
+    Extension(caller): Extension<Caller>,
     Query(req): Query<HistoryGetReq>,
 ) -> core::result::Result<Res<HistoryItem>, Res<String>> {
-    match GLOBAL_HISTORY.get_history(req.name).await {
+    match caller.history(&req.name).await {
         Ok(d) => Ok(Res::success(d)),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -0,0 +1,41 @@
+use axum::{body::Bytes, extract::Query, Extension};
+
+use crate::data::history::{
+    import::{import, ImportFormat, ImportReport},
+    store::SharedStore,
+};
+
+use super::super::*;
+
+// largest file accepted by `post_history_import`
+pub const IMPORT_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryImportReq {
+    pub name: String,
+    // csv when not given
+    pub format: Option<ImportFormat>,
+    pub dry_run: Option<bool>,
+}
+
+// the file is the raw request body
+pub async fn post_history_import(
+    Extension(store): Extension<SharedStore>,
+    Query(req): Query<HistoryImportReq>,
+    body: Bytes,
+) -> core::result::Result<Res<ImportReport>, Res<String>> {
+    tracing::info!("{:?}", &req);
+    let format = req.format.unwrap_or(ImportFormat::Csv);
+    let dry_run = req.dry_run.unwrap_or(false);
+
+    match import(&store, &req.name, format, &body, dry_run).await {
+        // the report lists the rows to fix
+        Ok(report) if report.invalid > 0 => Ok(Res {
+            code: StatusCode::BAD_REQUEST.as_u16(),
+            message: format!("{} invalid rows, nothing imported", report.invalid),
+            data: Some(report),
+        }),
+        Ok(report) => Ok(Res::success(report)),
+        Err(e) => Err(Res::custom_fail(StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
@@ -1,3 +1,17 @@
+pub mod aggregate;
+pub mod export;
 pub mod get;
+pub mod import;
 pub mod post;
+pub mod retention;
 pub mod subscribe;
+
+// `miners` plus `miner`, if it is not among them yet, all miners when empty
+pub fn miners(mut miners: Vec<String>, miner: Option<String>) -> Vec<String> {
+    if let Some(miner) = miner {
+        if !miners.contains(&miner) {
+            miners.push(miner);
+        }
+    }
+    miners
+}
@@ -1,19 +1,26 @@
-use std::sync::Arc;
-
-use crate::data::{
-    filfox::models::MinerInfo,
-    history::db::{get_db, init_history_db},
+use crate::{
+    apis::auth::Caller,
+    data::{filfox::models::MinerInfo, history::store::SharedStore},
 };
 
 use super::super::*;
-use axum::{extract::Query, Extension};
-use sqlx::SqlitePool;
+use axum::Extension;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HistoryGetReq {
     pub name: String,
     pub from: i64,
     pub to: i64,
+    // a single miner, or
+    pub miner: Option<String>,
+    // several miners, all miners are summed up when neither is given
+    pub miners: Option<Vec<String>>,
+}
+
+impl HistoryGetReq {
+    pub fn miners(&self) -> Vec<String> {
+        super::miners(self.miners.clone().unwrap_or_default(), self.miner.clone())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,9 +31,11 @@ pub struct HistoryGetRes {
 }
 
 pub async fn post_history(
+    Extension(store): Extension<SharedStore>,
+    Extension(caller): Extension<Caller>,
     Json(req): Json<HistoryGetReq>,
 ) -> core::result::Result<Res<HistoryGetRes>, Res<String>> {
-    match post_history_handler(req).await {
+    match post_history_handler(&caller, req, store).await {
         Ok(d) => Ok(Res::success(d)),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -36,12 +45,15 @@ pub async fn post_history(
 }
 // .on(MethodFilter::POST, apis::history::post::post_history)
 pub async fn post_history_handler(
+    caller: &Caller,
     req: HistoryGetReq,
-    // db: SqlitePool,
+    store: SharedStore,
 ) -> anyhow::Result<HistoryGetRes> {
     tracing::info!("{:?}", &req);
-    let db = init_history_db().await?;
-    let (time_vec, info_vec) = get_db(db, req.name.clone(), req.from, req.to).await?;
+    caller.history(&req.name).await?;
+    let (time_vec, info_vec) = store
+        .get(req.name.clone(), req.miners(), req.from, req.to)
+        .await?;
 
     Ok(HistoryGetRes {
         name: req.name,
@@ -0,0 +1,74 @@
+use axum::{extract::Query, Extension};
+
+use crate::{
+    apis::auth::Caller,
+    data::history::{retention::Retention, subscribe::GLOBAL_HISTORY},
+};
+
+use super::super::*;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryRetention {
+    pub name: String,
+    pub retention: Retention,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetHistoryRetentionReq {
+    // all subscriptions when not given
+    pub name: Option<String>,
+}
+
+pub async fn get_history_retention(
+    Extension(caller): Extension<Caller>,
+    Query(req): Query<GetHistoryRetentionReq>,
+) -> core::result::Result<Res<Vec<HistoryRetention>>, Res<String>> {
+    match get_history_retention_handler(&caller, req).await {
+        Ok(d) => Ok(Res::success(d)),
+        Err(e) => Err(Res::custom_fail(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+pub async fn get_history_retention_handler(
+    caller: &Caller,
+    req: GetHistoryRetentionReq,
+) -> anyhow::Result<Vec<HistoryRetention>> {
+    let items = match req.name {
+        Some(name) => vec![caller.history(&name).await?],
+        None => caller.histories().await,
+    };
+
+    Ok(items
+        .into_iter()
+        .map(|i| HistoryRetention {
+            name: i.name,
+            retention: i.retention,
+        })
+        .collect())
+}
+
+pub async fn post_history_retention(
+    Json(req): Json<HistoryRetention>,
+) -> core::result::Result<Res<HistoryRetention>, Res<String>> {
+    match post_history_retention_handler(req).await {
+        Ok(d) => Ok(Res::success(d)),
+        Err(e) => Err(Res::custom_fail(StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
+
+// change the policy of one subscription, applied by the next compaction run
+pub async fn post_history_retention_handler(
+    req: HistoryRetention,
+) -> anyhow::Result<HistoryRetention> {
+    let item = GLOBAL_HISTORY
+        .set_retention(&req.name, req.retention)
+        .await?;
+
+    Ok(HistoryRetention {
+        name: item.name,
+        retention: item.retention,
+    })
+}
@@ -4,12 +4,15 @@ use super::*;
 pub struct HistorySubscribeAddReq {
     pub name: String,
     pub interval: i64,
+    // keep everything when not given
+    pub retention: Option<Retention>,
 }
 
 pub async fn post_history_subscribe_add(
+    Extension(caller): Extension<Caller>,
     Json(req): Json<HistorySubscribeAddReq>,
 ) -> core::result::Result<Res<Vec<HistoryItem>>, Res<String>> {
-    match post_history_subscribe_add_handler(req).await {
+    match post_history_subscribe_add_handler(&caller, req).await {
         Ok(d) => Ok(Res::success(d)),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -18,13 +21,22 @@ pub async fn post_history_subscribe_add(
     }
 }
 
+// a user's subscription records and shows only its watchlist
 pub async fn post_history_subscribe_add_handler(
+    caller: &Caller,
     req: HistorySubscribeAddReq,
 ) -> anyhow::Result<Vec<HistoryItem>> {
     // add subscribe
-    GLOBAL_HISTORY.add(req.name, req.interval).await?;
+    GLOBAL_HISTORY
+        .add(
+            req.name,
+            req.interval,
+            req.retention.unwrap_or_default(),
+            caller.user.clone(),
+        )
+        .await?;
 
-    let nodes = GLOBAL_HISTORY.get().await;
+    let nodes = caller.histories().await;
 
     Ok(nodes)
 }
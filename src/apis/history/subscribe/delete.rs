@@ -6,9 +6,10 @@ pub struct HistorySubscribeDeleteReq {
 }
 
 pub async fn post_history_subscribe_delete(
+    Extension(caller): Extension<Caller>,
     Json(req): Json<HistorySubscribeDeleteReq>,
 ) -> core::result::Result<Res<Vec<HistoryItem>>, Res<String>> {
-    match post_history_subscribe_delete_handler(req).await {
+    match post_history_subscribe_delete_handler(&caller, req).await {
         Ok(d) => Ok(Res::success(d)),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -17,12 +18,21 @@ pub async fn post_history_subscribe_delete(
     }
 }
 
+// callers only delete subscriptions they see
 pub async fn post_history_subscribe_delete_handler(
+    caller: &Caller,
     req: HistorySubscribeDeleteReq,
 ) -> anyhow::Result<Vec<HistoryItem>> {
-    GLOBAL_HISTORY.delete(req.names).await?;
+    let names = caller
+        .histories()
+        .await
+        .into_iter()
+        .map(|i| i.name)
+        .filter(|name| req.names.contains(name))
+        .collect();
+    GLOBAL_HISTORY.delete(names).await?;
 
-    let data = { GLOBAL_HISTORY.get().await };
+    let data = { caller.histories().await };
 
     Ok(data)
 }
@@ -1,4 +1,12 @@
-use crate::data::history::subscribe::{HistoryItem, GLOBAL_HISTORY};
+use axum::Extension;
+
+use crate::{
+    apis::auth::Caller,
+    data::history::{
+        retention::Retention,
+        subscribe::{HistoryItem, GLOBAL_HISTORY},
+    },
+};
 
 use super::super::*;
 
@@ -11,8 +19,9 @@ pub struct GetHistorySubscribeRes {
 }
 
 pub async fn get_history_subscribe(
+    Extension(caller): Extension<Caller>,
 ) -> core::result::Result<Res<GetHistorySubscribeRes>, Res<String>> {
-    match get_history_subscribe_handler().await {
+    match get_history_subscribe_handler(&caller).await {
         Ok(d) => Ok(Res::success(GetHistorySubscribeRes { subscribe: d })),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -21,8 +30,8 @@ pub async fn get_history_subscribe(
     }
 }
 
-pub async fn get_history_subscribe_handler() -> anyhow::Result<Vec<HistoryItem>> {
-    let histories = GLOBAL_HISTORY.get().await;
+pub async fn get_history_subscribe_handler(caller: &Caller) -> anyhow::Result<Vec<HistoryItem>> {
+    let histories = caller.histories().await;
 
     Ok(histories)
 }
@@ -1,15 +1,30 @@
 use super::*;
+use crate::apis::auth::Caller;
 use crate::data::filfox::models::{MinerInfo, GLOBAL_MINER_INFOS};
+use axum::{extract::Query, Extension};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GetInfoReq {
+    // whether stale last-known-good data counts towards `total`, default true
+    pub include_stale: Option<bool>,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GetInfoRes {
     pub total: MinerInfo,
     pub info: Vec<MinerInfo>,
     pub last_update: String,
+    // nodes whose latest fetch failed
+    pub stale: Vec<String>,
+    pub include_stale: bool,
 }
 
-pub async fn get_info() -> core::result::Result<Res<GetInfoRes>, Res<String>> {
-    match get_info_handler().await {
+pub async fn get_info(
+    Extension(caller): Extension<Caller>,
+    Query(req): Query<GetInfoReq>,
+) -> core::result::Result<Res<GetInfoRes>, Res<String>> {
+    let nodes = caller.nodes().await;
+    match get_info_handler(req.include_stale.unwrap_or(true), nodes.as_deref()).await {
         Ok(d) => Ok(Res::success(d)),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -18,21 +33,35 @@ pub async fn get_info() -> core::result::Result<Res<GetInfoRes>, Res<String>> {
     }
 }
 
-pub async fn get_info_handler() -> anyhow::Result<GetInfoRes> {
-    let info = GLOBAL_MINER_INFOS.info().await?;
+// only `nodes` when given, all polled nodes otherwise
+pub async fn get_info_handler(
+    include_stale: bool,
+    nodes: Option<&[String]>,
+) -> anyhow::Result<GetInfoRes> {
+    let mut info = GLOBAL_MINER_INFOS.info().await?;
+    if let Some(nodes) = nodes {
+        info.retain(|i| nodes.contains(&i.id));
+    }
     let last_update = GLOBAL_MINER_INFOS.last_update().await?;
 
     let mut total = MinerInfo::new();
+    let mut stale = vec![];
     for i in &info {
-        total.pledge += i.pledge;
-        total.blocks += i.blocks;
-        total.power += i.power;
-        total.rewards += i.rewards;
+        if i.stale {
+            stale.push(i.id.clone());
+            total.stale = true;
+            if !include_stale {
+                continue;
+            }
+        }
+        total.add(i);
     }
 
     Ok(GetInfoRes {
         info,
         last_update,
         total,
+        stale,
+        include_stale,
     })
 }
@@ -0,0 +1,52 @@
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::Utc;
+
+use crate::{
+    apis::body::BodyWriter,
+    data::{backup::snapshot, history::store::SharedStore},
+};
+
+use super::super::*;
+
+// .on(MethodFilter::GET, apis::inner::backup::get_backup)
+pub async fn get_backup(
+    Extension(store): Extension<SharedStore>,
+) -> core::result::Result<Response, Res<String>> {
+    // snapshot before answering so a failed copy is a proper error
+    let snapshot = match snapshot(&store).await {
+        Ok(s) => s,
+        Err(e) => {
+            return Err(Res::custom_fail(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    };
+    tracing::info!("backup {:?}", snapshot.manifest);
+
+    let headers = [
+        (header::CONTENT_TYPE, "application/gzip".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"node-monitor-{}.tar.gz\"",
+                Utc::now().format("%Y%m%d-%H%M%S")
+            ),
+        ),
+    ];
+
+    let (writer, tx, body) = BodyWriter::channel();
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = snapshot.write(writer) {
+            tracing::error!("backup error: {}", e);
+            // abort the body so a cut archive is not taken for a complete one
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+
+    Ok((headers, body).into_response())
+}
@@ -0,0 +1,65 @@
+use axum::http::{header, header::HeaderName, HeaderMap};
+use serde_json::Value;
+
+use crate::data::config::{Config, ConfigUpdateError, GLOBAL_CONFIG};
+
+use super::super::*;
+
+#[derive(Debug, Serialize)]
+pub struct ConfigRes {
+    // also sent as the etag, send it back in If-Match to update
+    pub version: String,
+    pub config: Config,
+}
+
+type ConfigResponse = ([(HeaderName, String); 1], Res<ConfigRes>);
+
+fn config_response(config: Config) -> ConfigResponse {
+    let version = config.version();
+    (
+        [(header::ETAG, format!("\"{}\"", version))],
+        Res::success(ConfigRes { version, config }),
+    )
+}
+
+pub(crate) fn config_error(e: ConfigUpdateError) -> Res<String> {
+    let code = match &e {
+        ConfigUpdateError::Stale(_) => StatusCode::PRECONDITION_FAILED,
+        ConfigUpdateError::Invalid(_) => StatusCode::BAD_REQUEST,
+        ConfigUpdateError::Save(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    Res::custom_fail(code, e.to_string())
+}
+
+// .on(MethodFilter::GET, apis::inner::config::get_config)
+pub async fn get_config() -> core::result::Result<ConfigResponse, Res<String>> {
+    Ok(config_response(GLOBAL_CONFIG.config().await))
+}
+
+// .on(MethodFilter::PATCH, apis::inner::config::patch_config)
+// a json merge patch of `Config`, only applied when If-Match, if given,
+// still names the current version
+pub async fn patch_config(
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> core::result::Result<ConfigResponse, Res<String>> {
+    tracing::info!("{}", &patch);
+    let expected = match headers.get(header::IF_MATCH) {
+        Some(v) => {
+            let v = v
+                .to_str()
+                .map_err(|_| Res::custom_fail(StatusCode::BAD_REQUEST, "bad If-Match".into()))?;
+            let v = v.trim().trim_start_matches("W/").trim_matches('"');
+            (v != "*").then(|| v.to_string())
+        }
+        None => None,
+    };
+
+    match GLOBAL_CONFIG
+        .update(expected.as_deref(), |config| config.patch(&patch))
+        .await
+    {
+        Ok(config) => Ok(config_response(config)),
+        Err(e) => Err(config_error(e)),
+    }
+}
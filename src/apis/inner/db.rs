@@ -0,0 +1,20 @@
+use crate::data::history::{integrity::DbStatus, store::SharedStore};
+
+use super::super::*;
+use axum::Extension;
+
+pub async fn get_db_status(
+    Extension(store): Extension<SharedStore>,
+) -> core::result::Result<Res<DbStatus>, Res<String>> {
+    match get_db_status_handler(store).await {
+        Ok(d) => Ok(Res::success(d)),
+        Err(e) => Err(Res::custom_fail(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+pub async fn get_db_status_handler(store: SharedStore) -> anyhow::Result<DbStatus> {
+    store.status().await
+}
@@ -1,6 +1,9 @@
-use crate::data::config::GLOBAL_CONFIG;
+use crate::data::config::{ConfigUpdateError, GLOBAL_CONFIG};
 
-use super::super::*;
+use super::{super::*, config::config_error};
+
+// how far `from` may be off, clients rarely send back the exact float
+const INTERVAL_EPSILON: f32 = 1e-3;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct IntervalReq {
@@ -19,40 +22,32 @@ pub async fn get_interval() -> core::result::Result<Res<IntervalRes>, Res<String
     Ok(Res::success(IntervalRes { interval }))
 }
 
-pub async fn post_interval_handler(req: IntervalReq) -> anyhow::Result<f32> {
-    let mut do_change = false;
-    if let Some(t) = req.force {
-        if t {
-            do_change = true;
-        }
-    }
-
-    let current_interval = GLOBAL_CONFIG.interval().await;
-    if current_interval == req.from {
-        do_change = true;
-    }
-
-    if do_change {
-        GLOBAL_CONFIG.set_interval(req.to).await?;
+// set the interval to `to` if it still is `from`, or anyway with `force`
+pub async fn post_interval_handler(req: IntervalReq) -> Result<f32, ConfigUpdateError> {
+    let current = GLOBAL_CONFIG.config().await;
+    if !req.force.unwrap_or(false) && (current.interval - req.from).abs() > INTERVAL_EPSILON {
+        return Err(ConfigUpdateError::Stale(format!(
+            "interval is {}, not {}",
+            current.interval, req.from
+        )));
     }
 
-    // save changes
-    GLOBAL_CONFIG.save().await?;
+    // fails as stale if anything changed since `current` was read
+    let config = GLOBAL_CONFIG
+        .update(Some(&current.version()), |config| {
+            config.interval = req.to;
+            Ok(())
+        })
+        .await?;
 
-    Ok(req.to)
+    Ok(config.interval)
 }
 
 pub async fn post_interval(
     Json(req): Json<IntervalReq>,
 ) -> core::result::Result<Res<IntervalRes>, Res<String>> {
     match post_interval_handler(req).await {
-        Ok(_) => Ok({
-            let interval = GLOBAL_CONFIG.interval().await;
-            Res::success(IntervalRes { interval })
-        }),
-        Err(e) => Err(Res::custom_fail(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            e.to_string(),
-        )),
+        Ok(interval) => Ok(Res::success(IntervalRes { interval })),
+        Err(e) => Err(config_error(e)),
     }
 }
@@ -0,0 +1,74 @@
+use axum::http::HeaderMap;
+
+use crate::data::login::{LoginError, Session, CODE_TTL, GLOBAL_LOGIN};
+
+use super::{auth::presented_key, *};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginCodeReq {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginCodeRes {
+    // seconds the mailed code stays valid
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginVerifyReq {
+    pub email: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginVerifyRes {
+    // send as `Authorization: Bearer <token>`
+    pub token: String,
+    pub session: Session,
+}
+
+fn login_error(e: LoginError) -> Res<String> {
+    let code = match &e {
+        LoginError::Disabled => StatusCode::SERVICE_UNAVAILABLE,
+        LoginError::InvalidEmail(_) => StatusCode::BAD_REQUEST,
+        LoginError::TooSoon(_) | LoginError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+        LoginError::BadCode => StatusCode::UNAUTHORIZED,
+        LoginError::Mail(_) => StatusCode::BAD_GATEWAY,
+    };
+    Res::custom_fail(code, e.to_string())
+}
+
+// .on(MethodFilter::POST, apis::login::post_login_code)
+pub async fn post_login_code(
+    Json(req): Json<LoginCodeReq>,
+) -> core::result::Result<Res<LoginCodeRes>, Res<String>> {
+    match GLOBAL_LOGIN.request_code(&req.email).await {
+        Ok(_) => Ok(Res::success(LoginCodeRes {
+            expires_in: CODE_TTL,
+        })),
+        Err(e) => Err(login_error(e)),
+    }
+}
+
+// .on(MethodFilter::POST, apis::login::post_login_verify)
+pub async fn post_login_verify(
+    Json(req): Json<LoginVerifyReq>,
+) -> core::result::Result<Res<LoginVerifyRes>, Res<String>> {
+    match GLOBAL_LOGIN.verify(&req.email, &req.code).await {
+        Ok((token, session)) => Ok(Res::success(LoginVerifyRes { token, session })),
+        Err(e) => Err(login_error(e)),
+    }
+}
+
+// .on(MethodFilter::POST, apis::login::post_logout)
+// end the session whose token is sent, whether it was known
+pub async fn post_logout(headers: HeaderMap) -> core::result::Result<Res<bool>, Res<String>> {
+    match presented_key(&headers) {
+        Some(token) => Ok(Res::success(GLOBAL_LOGIN.logout(token).await)),
+        None => Err(Res::custom_fail(
+            StatusCode::UNAUTHORIZED,
+            "missing session token".to_string(),
+        )),
+    }
+}
@@ -3,7 +3,13 @@ use axum::Json;
 use han_utils::res::Res;
 use serde::{Deserialize, Serialize};
 
+pub mod auth;
+pub mod body;
+pub mod breakers;
 pub mod history;
 pub mod info;
 pub mod inner;
+pub mod login;
+pub mod sources;
 pub mod subscribe;
+pub mod users;
@@ -0,0 +1,16 @@
+use super::*;
+use crate::data::source::health::{SourceHealth, GLOBAL_SOURCE_HEALTH};
+
+pub async fn get_sources() -> core::result::Result<Res<Vec<SourceHealth>>, Res<String>> {
+    match get_sources_handler().await {
+        Ok(d) => Ok(Res::success(d)),
+        Err(e) => Err(Res::custom_fail(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+pub async fn get_sources_handler() -> anyhow::Result<Vec<SourceHealth>> {
+    Ok(GLOBAL_SOURCE_HEALTH.get().await)
+}
@@ -1,16 +1,28 @@
-use crate::data::nodes::GLOBAL_NODES;
+use crate::{
+    apis::auth::Caller,
+    data::{nodes::GLOBAL_NODES, source::check_source_names, users::GLOBAL_USERS},
+};
 
 use super::*;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SubscribeAddReq {
     pub id: String,
+    // ordered source names to poll this node from
+    pub sources: Option<Vec<String>>,
 }
 
 pub async fn post_subscribe_add(
+    Extension(caller): Extension<Caller>,
     Json(req): Json<SubscribeAddReq>,
 ) -> core::result::Result<Res<Vec<String>>, Res<String>> {
-    match post_subscribe_add_handler(req).await {
+    if caller.user.is_some() && req.sources.is_some() {
+        return Err(Res::custom_fail(
+            StatusCode::FORBIDDEN,
+            format!("{} may not set the sources of a node", caller.key),
+        ));
+    }
+    match post_subscribe_add_handler(&caller, req).await {
         Ok(d) => Ok(Res::success(d)),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -19,7 +31,29 @@ pub async fn post_subscribe_add(
     }
 }
 
-pub async fn post_subscribe_add_handler(req: SubscribeAddReq) -> anyhow::Result<Vec<String>> {
+// users add to their watchlist, other callers to nodes.json, the sources of
+// a node are shared by everyone polling it so users may not set them
+pub async fn post_subscribe_add_handler(
+    caller: &Caller,
+    req: SubscribeAddReq,
+) -> anyhow::Result<Vec<String>> {
+    if req.id.trim().is_empty() {
+        anyhow::bail!("empty node id");
+    }
+    if let Some(user) = &caller.user {
+        if req.sources.is_some() {
+            anyhow::bail!("{} may not set the sources of a node", caller.key);
+        }
+        return GLOBAL_USERS.watch(user, req.id).await;
+    }
+    if let Some(sources) = &req.sources {
+        check_source_names(sources)?;
+        GLOBAL_NODES
+            .sources
+            .write()
+            .await
+            .insert(req.id.clone(), sources.clone());
+    }
     {
         let mut nodes = GLOBAL_NODES.nodes.write().await;
         if !nodes.contains(&req.id) {
@@ -1,4 +1,7 @@
-use crate::data::nodes::GLOBAL_NODES;
+use crate::{
+    apis::auth::Caller,
+    data::{nodes::GLOBAL_NODES, users::GLOBAL_USERS},
+};
 
 use super::*;
 
@@ -8,9 +11,10 @@ pub struct SubscribeDeleteReq {
 }
 
 pub async fn post_subscribe_delete(
+    Extension(caller): Extension<Caller>,
     Json(req): Json<SubscribeDeleteReq>,
 ) -> core::result::Result<Res<Vec<String>>, Res<String>> {
-    match post_subscribe_delete_handler(req).await {
+    match post_subscribe_delete_handler(&caller, req).await {
         Ok(d) => Ok(Res::success(d)),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -19,7 +23,15 @@ pub async fn post_subscribe_delete(
     }
 }
 
-pub async fn post_subscribe_delete_handler(req: SubscribeDeleteReq) -> anyhow::Result<Vec<String>> {
+// users drop nodes from their watchlist, other callers from nodes.json,
+// source preferences stay while users may still watch the node
+pub async fn post_subscribe_delete_handler(
+    caller: &Caller,
+    req: SubscribeDeleteReq,
+) -> anyhow::Result<Vec<String>> {
+    if let Some(user) = &caller.user {
+        return GLOBAL_USERS.unwatch(user, &req.ids).await;
+    }
     let nodes = { GLOBAL_NODES.nodes.read().await.clone() };
     let delete_ids = req.ids;
 
@@ -31,6 +43,14 @@ pub async fn post_subscribe_delete_handler(req: SubscribeDeleteReq) -> anyhow::R
     {
         *GLOBAL_NODES.nodes.write().await = nodes;
     }
+    {
+        let watched = GLOBAL_USERS.watched().await;
+        GLOBAL_NODES
+            .sources
+            .write()
+            .await
+            .retain(|id, _| !delete_ids.contains(id) || watched.contains(id));
+    }
 
     GLOBAL_NODES.save().await?;
     let nodes = { GLOBAL_NODES.nodes.read().await.clone() };
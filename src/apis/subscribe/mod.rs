@@ -1,15 +1,17 @@
 use axum::http::StatusCode;
-use axum::Json;
+use axum::{Extension, Json};
 use han_utils::res::Res;
 use serde::{Deserialize, Serialize};
 
-use crate::data::nodes::GLOBAL_NODES;
+use crate::{apis::auth::Caller, data::nodes::GLOBAL_NODES};
 
 pub mod add;
 pub mod delete;
 
-pub async fn get_subscribe() -> core::result::Result<Res<Vec<String>>, Res<String>> {
-    match get_subscribe_handler().await {
+pub async fn get_subscribe(
+    Extension(caller): Extension<Caller>,
+) -> core::result::Result<Res<Vec<String>>, Res<String>> {
+    match get_subscribe_handler(&caller).await {
         Ok(d) => Ok(Res::success(d)),
         Err(e) => Err(Res::custom_fail(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -18,7 +20,11 @@ pub async fn get_subscribe() -> core::result::Result<Res<Vec<String>>, Res<Strin
     }
 }
 
-pub async fn get_subscribe_handler() -> anyhow::Result<Vec<String>> {
+// a user's watchlist, or nodes.json
+pub async fn get_subscribe_handler(caller: &Caller) -> anyhow::Result<Vec<String>> {
+    if let Some(nodes) = caller.nodes().await {
+        return Ok(nodes);
+    }
     let nodes = GLOBAL_NODES.nodes.read().await.clone();
 
     Ok(nodes)
@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+use crate::data::{history::subscribe::GLOBAL_HISTORY, keys::GLOBAL_KEYS, users::GLOBAL_USERS};
+
+use super::*;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserRes {
+    pub name: String,
+    // watchlist
+    pub nodes: Vec<String>,
+    // names of the history subscriptions it added
+    pub subscriptions: Vec<String>,
+    // ids of its unrevoked api keys
+    pub keys: Vec<String>,
+}
+
+// .on(MethodFilter::GET, apis::users::get_users)
+pub async fn get_users() -> core::result::Result<Res<Vec<UserRes>>, Res<String>> {
+    match get_users_handler().await {
+        Ok(d) => Ok(Res::success(d)),
+        Err(e) => Err(Res::custom_fail(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            e.to_string(),
+        )),
+    }
+}
+
+fn entry<'a>(users: &'a mut BTreeMap<String, UserRes>, name: &str) -> &'a mut UserRes {
+    users.entry(name.to_string()).or_insert_with(|| UserRes {
+        name: name.to_string(),
+        ..Default::default()
+    })
+}
+
+// every user named by a key or holding a watchlist or subscription
+pub async fn get_users_handler() -> anyhow::Result<Vec<UserRes>> {
+    let mut users: BTreeMap<String, UserRes> = BTreeMap::new();
+    for (name, u) in GLOBAL_USERS.users().await.users {
+        entry(&mut users, &name).nodes = u.nodes;
+    }
+    for item in GLOBAL_HISTORY.get().await {
+        if let Some(owner) = &item.owner {
+            entry(&mut users, owner).subscriptions.push(item.name);
+        }
+    }
+    for k in GLOBAL_KEYS.keys().await.keys {
+        if let (Some(owner), None) = (&k.user, k.revoked_at) {
+            entry(&mut users, owner).keys.push(k.id);
+        }
+    }
+
+    Ok(users.into_values().collect())
+}
@@ -0,0 +1,242 @@
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+};
+
+use chrono::{TimeZone, Utc};
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    data::{
+        backup::{create_backup, restore_backup, BackupTargets},
+        config::GLOBAL_CONFIG,
+        history::{
+            export::{export, ExportFormat, ExportQuery},
+            import::{import, ImportFormat},
+            store::{open_history_store, redact_url},
+        },
+        keys::{Keys, Scope},
+    },
+    settings::{settings, SettingsArgs},
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "filecoin node monitor")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub settings: SettingsArgs,
+    /// Print the effective settings as TOML and exit
+    #[arg(long, global = true)]
+    pub print_config: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the monitor server, the default
+    Serve,
+    /// Write history rows to a file or stdout
+    Export(ExportArgs),
+    /// Backfill history rows from a file or stdin
+    Import(ImportArgs),
+    /// Write a snapshot of all state to one archive, safe while serving
+    Backup(BackupArgs),
+    /// Check a backup archive and swap it in, stop the server first
+    Restore(RestoreArgs),
+    /// Manage api keys, a running server picks up changes
+    #[command(subcommand)]
+    Keys(KeysCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeysCommand {
+    /// Create a key and print it, it is only stored hashed
+    Create {
+        /// What the key is for
+        #[arg(long)]
+        name: String,
+        /// Scope of the key, repeatable
+        #[arg(long = "scope", value_enum, required = true)]
+        scopes: Vec<Scope>,
+        /// Limit the key to this user's watchlist and subscriptions
+        #[arg(long)]
+        user: Option<String>,
+    },
+    /// List the keys, revoked ones included
+    List,
+    /// Revoke a key by id
+    Revoke { id: String },
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// History subscription name
+    #[arg(long)]
+    pub name: String,
+    /// Only these miners, repeatable, all miners when not given
+    #[arg(long = "miner")]
+    pub miners: Vec<String>,
+    /// First timestamp, inclusive
+    #[arg(long, default_value_t = 0)]
+    pub from: i64,
+    /// Last timestamp, exclusive, defaults to now
+    #[arg(long)]
+    pub to: Option<i64>,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+    pub format: ExportFormat,
+    /// Output file, stdout when not given
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// History subscription name, it must exist
+    #[arg(long)]
+    pub name: String,
+    #[arg(long, value_enum, default_value_t = ImportFormat::Csv)]
+    pub format: ImportFormat,
+    /// Input file, stdin when not given
+    #[arg(long, short)]
+    pub input: Option<PathBuf>,
+    /// Only validate the rows
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    /// Output file, stdout when not given
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreArgs {
+    /// Backup archive, stdin when not given
+    #[arg(long, short)]
+    pub input: Option<PathBuf>,
+}
+
+pub async fn export_history(args: ExportArgs) -> anyhow::Result<()> {
+    let store = open_history_store().await?;
+    let query = ExportQuery {
+        name: args.name,
+        miners: args.miners,
+        from: args.from,
+        to: args.to.unwrap_or_else(|| Utc::now().timestamp() + 1),
+        format: args.format,
+    };
+
+    let out: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    let written = export(store, query, out).await?;
+    tracing::info!("exported {} rows", written);
+
+    Ok(())
+}
+
+pub async fn import_history(args: ImportArgs) -> anyhow::Result<()> {
+    let store = open_history_store().await?;
+
+    let mut input = vec![];
+    match &args.input {
+        Some(path) => std::fs::File::open(path)?.read_to_end(&mut input)?,
+        None => std::io::stdin().read_to_end(&mut input)?,
+    };
+
+    let report = import(&store, &args.name, args.format, &input, args.dry_run).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if report.invalid > 0 {
+        anyhow::bail!("{} invalid rows, nothing imported", report.invalid);
+    }
+
+    Ok(())
+}
+
+pub async fn backup(args: BackupArgs) -> anyhow::Result<()> {
+    let store = open_history_store().await?;
+
+    let out: Box<dyn Write + Send> = match &args.output {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    let manifest = create_backup(&store, out).await?;
+    tracing::info!("backup written: {:?}", manifest);
+
+    Ok(())
+}
+
+pub async fn restore(args: RestoreArgs) -> anyhow::Result<()> {
+    let input: Box<dyn Read> = match &args.input {
+        Some(path) => Box::new(std::fs::File::open(path)?),
+        None => Box::new(std::io::stdin()),
+    };
+
+    let report = restore_backup(input, &BackupTargets::configured()).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    Ok(())
+}
+
+pub async fn keys(command: KeysCommand) -> anyhow::Result<()> {
+    let mut keys = Keys::load()?;
+    match command {
+        KeysCommand::Create { name, scopes, user } => {
+            let (api_key, key) = keys.create(&name, &scopes, user.as_deref())?;
+            keys.save()?;
+            tracing::info!("created key {} ({})", api_key.id, api_key.name);
+            println!("{}", key);
+        }
+        KeysCommand::List => {
+            for k in &keys.keys {
+                let scopes: Vec<String> = k.scopes.iter().map(|s| s.to_string()).collect();
+                let created = Utc.timestamp_opt(k.created_at, 0).unwrap();
+                let user = match &k.user {
+                    Some(user) => format!("  user {}", user),
+                    None => String::new(),
+                };
+                let revoked = match k.revoked_at {
+                    Some(t) => format!("  revoked {}", Utc.timestamp_opt(t, 0).unwrap()),
+                    None => String::new(),
+                };
+                println!(
+                    "{}  {}  {}{}  created {}{}",
+                    k.id,
+                    k.name,
+                    scopes.join(","),
+                    user,
+                    created,
+                    revoked
+                );
+            }
+        }
+        KeysCommand::Revoke { id } => {
+            let api_key = keys.revoke(&id)?;
+            keys.save()?;
+            tracing::info!("revoked key {} ({})", api_key.id, api_key.name);
+        }
+    }
+
+    Ok(())
+}
+
+// the settings in effect, with the timeouts, interval, sources and lotus
+// api the server would use
+pub async fn print_config() -> anyhow::Result<()> {
+    let mut effective = settings().clone();
+    effective.history_db = redact_url(&effective.history_db_url());
+    effective.login.smtp_url = effective.login.smtp_url.as_deref().map(redact_url);
+    effective.lotus.token = effective.lotus.token.map(|_| "***".to_string());
+    effective.lotus.api = Some(GLOBAL_CONFIG.lotus_api().await);
+    effective.timeouts.filfox = Some(GLOBAL_CONFIG.timeouts.filfox().await);
+    effective.timeouts.lotus = Some(GLOBAL_CONFIG.timeouts.lotus().await);
+    effective.interval = Some(GLOBAL_CONFIG.interval().await);
+    effective.sources = GLOBAL_CONFIG.sources().await;
+    print!("{}", toml::to_string_pretty(&effective)?);
+
+    Ok(())
+}
@@ -0,0 +1,507 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chrono::Utc;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use super::{
+    config::{check_config_file, load_legacy_config, CONFIG_FILE, GLOBAL_CONFIG},
+    history::{
+        db::HISTORY_DB,
+        integrity::{check_integrity, IntegrityCheck},
+        migrate::{latest_version, schema_version},
+        store::{is_postgres_url, SharedStore},
+        subscribe::{check_history_file, load_legacy_history, GLOBAL_HISTORY, HISTORY_FILE},
+    },
+    nodes::{check_nodes_file, load_legacy_nodes, GLOBAL_NODES, NODES_FILE},
+    state::save_json,
+    users::{check_users_file, GLOBAL_USERS, USERS_FILE},
+};
+
+// bump when the archive layout changes, 1 held savefiles instead of json,
+// 2 had no users.json
+pub const BACKUP_VERSION: u32 = 3;
+// first entry of every archive
+const MANIFEST: &str = "manifest.json";
+// entry names inside the archive, whatever the files are called on disk
+const HISTORY_DB_ENTRY: &str = "history.db";
+const CONFIG_ENTRY: &str = "config.json";
+const NODES_ENTRY: &str = "nodes.json";
+const HISTORY_ENTRY: &str = "history.json";
+const USERS_ENTRY: &str = "users.json";
+// savefiles of version 1 archives, converted to json on restore
+const LEGACY_ENTRIES: &[(&str, &str)] = &[
+    ("config.bin", CONFIG_ENTRY),
+    ("nodes.bin", NODES_ENTRY),
+    ("history.bin", HISTORY_ENTRY),
+];
+// suffix of the files a restore replaced, kept until the next restore
+const PRE_RESTORE: &str = ".pre-restore";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub created_at: i64,
+    // version of the build that wrote the backup
+    pub app_version: String,
+    // history backend, history.db is only in the archive for sqlite
+    pub backend: String,
+    pub schema_version: i64,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreReport {
+    pub manifest: BackupManifest,
+    // files swapped in
+    pub restored: Vec<String>,
+    // files in the archive that have nowhere to go
+    pub skipped: Vec<String>,
+    // files moved aside by the restore
+    pub previous: Vec<String>,
+}
+
+// where a restore puts each file, the configured state files by default
+#[derive(Debug, Clone)]
+pub struct BackupTargets {
+    // none with a postgres history, restore that with pg_restore
+    pub history_db: Option<PathBuf>,
+    pub config: PathBuf,
+    pub nodes: PathBuf,
+    pub history: PathBuf,
+    pub users: PathBuf,
+}
+
+impl BackupTargets {
+    pub fn configured() -> Self {
+        Self {
+            history_db: (!is_postgres_url(&HISTORY_DB)).then(|| sqlite_path(&HISTORY_DB)),
+            config: PathBuf::from(&*CONFIG_FILE),
+            nodes: PathBuf::from(&*NODES_FILE),
+            history: PathBuf::from(&*HISTORY_FILE),
+            users: PathBuf::from(&*USERS_FILE),
+        }
+    }
+
+    fn target(&self, entry: &str) -> anyhow::Result<Option<&Path>> {
+        Ok(match entry {
+            HISTORY_DB_ENTRY => self.history_db.as_deref(),
+            CONFIG_ENTRY => Some(&self.config),
+            NODES_ENTRY => Some(&self.nodes),
+            HISTORY_ENTRY => Some(&self.history),
+            USERS_ENTRY => Some(&self.users),
+            _ => anyhow::bail!("unknown file {:?} in backup", entry),
+        })
+    }
+}
+
+// file behind a sqlite `HISTORY_DB`, which may be a plain path or a url
+fn sqlite_path(url: &str) -> PathBuf {
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .unwrap_or(url);
+    PathBuf::from(path.split('?').next().unwrap_or(path))
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+// a scratch directory, removed again on drop
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(prefix: &str) -> anyhow::Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "{}-{}-{}",
+            prefix,
+            std::process::id(),
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        fs::create_dir_all(&dir)?;
+        Ok(Scratch(dir))
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// a consistent copy of all state, waiting to be written out as an archive
+pub struct Snapshot {
+    dir: Scratch,
+    pub manifest: BackupManifest,
+}
+
+// copy history.db and the state files aside while the server keeps running
+pub async fn snapshot(store: &SharedStore) -> anyhow::Result<Snapshot> {
+    let dir = Scratch::new("node-monitor-backup")?;
+    let status = store.status().await?;
+
+    let mut names = vec![];
+    if store.snapshot(&dir.0.join(HISTORY_DB_ENTRY)).await? {
+        names.push(HISTORY_DB_ENTRY);
+    } else {
+        tracing::warn!(
+            "{} history is not part of the backup, back it up with the database tools",
+            store.backend()
+        );
+    }
+    let path = |entry: &str| dir.0.join(entry).to_string_lossy().to_string();
+    GLOBAL_CONFIG.save_to(&path(CONFIG_ENTRY)).await?;
+    GLOBAL_NODES.save_to(&path(NODES_ENTRY)).await?;
+    GLOBAL_HISTORY.save_to(&path(HISTORY_ENTRY)).await?;
+    GLOBAL_USERS.save_to(&path(USERS_ENTRY)).await?;
+    names.extend([CONFIG_ENTRY, NODES_ENTRY, HISTORY_ENTRY, USERS_ENTRY]);
+
+    let mut files = vec![];
+    for name in names {
+        let size = fs::metadata(dir.0.join(name))?.len();
+        files.push(BackupFile {
+            name: name.to_string(),
+            size,
+        });
+    }
+
+    let manifest = BackupManifest {
+        version: BACKUP_VERSION,
+        created_at: Utc::now().timestamp(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        backend: store.backend().to_string(),
+        schema_version: status.schema_version,
+        files,
+    };
+    Ok(Snapshot { dir, manifest })
+}
+
+impl Snapshot {
+    // a .tar.gz with the manifest first, blocking
+    pub fn write<W: Write>(&self, out: W) -> anyhow::Result<()> {
+        let mut tar = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+
+        let manifest = serde_json::to_vec_pretty(&self.manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(self.manifest.created_at.max(0) as u64);
+        header.set_cksum();
+        tar.append_data(&mut header, MANIFEST, manifest.as_slice())?;
+
+        for file in &self.manifest.files {
+            tar.append_path_with_name(self.dir.0.join(&file.name), &file.name)?;
+        }
+
+        tar.into_inner()?.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+// snapshot all state and write it to `out` as one archive
+pub async fn create_backup<W: Write + Send + 'static>(
+    store: &SharedStore,
+    out: W,
+) -> anyhow::Result<BackupManifest> {
+    let snapshot = snapshot(store).await?;
+    tokio::task::spawn_blocking(move || {
+        snapshot.write(out)?;
+        Ok(snapshot.manifest.clone())
+    })
+    .await?
+}
+
+// files unpacked next to their targets, removed unless swapped in
+#[derive(Default)]
+struct Staged {
+    // (entry, staged file, target)
+    files: Vec<(String, PathBuf, PathBuf)>,
+    // savefiles left over from converting a version 1 archive
+    scratch: Vec<PathBuf>,
+}
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        let staged = self.files.iter().map(|(_, staged, _)| staged);
+        for path in staged.chain(&self.scratch) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// write the json an older savefile entry stands for
+fn convert_legacy(entry: &str, bin: &Path, json: &Path) -> anyhow::Result<()> {
+    let (bin, json) = (bin.to_string_lossy(), json.to_string_lossy());
+    match entry {
+        CONFIG_ENTRY => save_json(&json, &load_legacy_config(&bin)?),
+        NODES_ENTRY => save_json(&json, &load_legacy_nodes(&bin)?),
+        _ => save_json(&json, &load_legacy_history(&bin)?),
+    }
+}
+
+// unpack an archive next to the targets and check every file before any
+// target is touched, the server must be stopped as it keeps the old files open
+pub async fn restore_backup<R: Read>(
+    input: R,
+    targets: &BackupTargets,
+) -> anyhow::Result<RestoreReport> {
+    let mut archive = tar::Archive::new(GzDecoder::new(input));
+    let mut manifest: Option<BackupManifest> = None;
+    let mut staged = Staged::default();
+    let mut unpacked = vec![];
+    let mut skipped = vec![];
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        // a link unpacked as history.db would have sqlite write through it
+        if !entry.header().entry_type().is_file() {
+            anyhow::bail!(
+                "{} in backup is a {:?}, not a regular file",
+                name,
+                entry.header().entry_type()
+            );
+        }
+
+        if name == MANIFEST {
+            let mut json = String::new();
+            entry.read_to_string(&mut json)?;
+            let m: BackupManifest = serde_json::from_str(&json)?;
+            if m.version == 0 || m.version > BACKUP_VERSION {
+                anyhow::bail!(
+                    "backup version {} is not supported, this build reads version {}",
+                    m.version,
+                    BACKUP_VERSION
+                );
+            }
+            manifest = Some(m);
+            continue;
+        }
+
+        let m = match &manifest {
+            Some(m) => m,
+            None => anyhow::bail!("not a backup, {} must come first", MANIFEST),
+        };
+        let file = match m.files.iter().find(|f| f.name == name) {
+            Some(f) => f.clone(),
+            None => anyhow::bail!("{} is in the archive but not in the manifest", name),
+        };
+        let legacy = LEGACY_ENTRIES.iter().find(|(bin, _)| *bin == name);
+        let restores = legacy.map(|(_, json)| *json).unwrap_or(&name);
+        let target = match targets.target(restores)? {
+            Some(t) => t.to_path_buf(),
+            None => {
+                skipped.push(name);
+                continue;
+            }
+        };
+
+        let path = with_suffix(&target, ".restore");
+        let unpack_to = match legacy {
+            Some(_) => with_suffix(&target, ".restore.bin"),
+            None => path.clone(),
+        };
+        entry.unpack(&unpack_to)?;
+        staged.scratch.push(unpack_to.clone());
+
+        let size = fs::metadata(&unpack_to)?.len();
+        if size != file.size {
+            anyhow::bail!(
+                "{} is {} bytes, the manifest says {}",
+                name,
+                size,
+                file.size
+            );
+        }
+        if legacy.is_some() {
+            convert_legacy(restores, &unpack_to, &path)
+                .map_err(|e| anyhow::anyhow!("{} in backup is not usable: {}", name, e))?;
+        }
+        staged.files.push((restores.to_string(), path, target));
+        unpacked.push(name);
+    }
+
+    let manifest = match manifest {
+        Some(m) => m,
+        None => anyhow::bail!("not a backup, {} is missing", MANIFEST),
+    };
+    for file in &manifest.files {
+        if !unpacked.contains(&file.name) && !skipped.contains(&file.name) {
+            anyhow::bail!("backup is truncated, {} is missing", file.name);
+        }
+    }
+
+    for (name, path, _) in &staged.files {
+        let path_str = path.to_string_lossy();
+        match name.as_str() {
+            HISTORY_DB_ENTRY => check_history_db(path).await,
+            CONFIG_ENTRY => check_config_file(&path_str),
+            NODES_ENTRY => check_nodes_file(&path_str),
+            USERS_ENTRY => check_users_file(&path_str),
+            _ => check_history_file(&path_str),
+        }
+        .map_err(|e| anyhow::anyhow!("{} in backup is not usable: {}", name, e))?;
+    }
+
+    // everything checked out, move the current files aside and swap in
+    let mut restored = vec![];
+    let mut previous = vec![];
+    for (name, path, target) in &staged.files {
+        // sqlite keeps recent writes next to the database, they go with it
+        let sidecars: &[&str] = if name == HISTORY_DB_ENTRY {
+            &["", "-wal", "-shm"]
+        } else {
+            &[""]
+        };
+        for sidecar in sidecars {
+            let current = with_suffix(target, sidecar);
+            if current.exists() {
+                let aside = with_suffix(target, &format!("{}{}", PRE_RESTORE, sidecar));
+                fs::rename(&current, &aside)?;
+                previous.push(aside.to_string_lossy().to_string());
+            }
+        }
+        fs::rename(path, target)?;
+        restored.push(target.to_string_lossy().to_string());
+    }
+
+    Ok(RestoreReport {
+        manifest,
+        restored,
+        skipped,
+        previous,
+    })
+}
+
+// a full integrity check and a schema this build can migrate
+async fn check_history_db(path: &Path) -> anyhow::Result<()> {
+    let options = SqliteConnectOptions::from_str(&path.to_string_lossy())?.read_only(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+
+    let report = check_integrity(&pool, IntegrityCheck::Full).await?;
+    let version = schema_version(&pool).await?;
+    pool.close().await;
+
+    if !report.ok {
+        anyhow::bail!("integrity check failed: {}", report.problems.join("; "));
+    }
+    if version > latest_version() {
+        anyhow::bail!(
+            "schema version {} is newer than this build knows ({})",
+            version,
+            latest_version()
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_backup_restore() -> anyhow::Result<()> {
+    use std::sync::Arc;
+
+    use super::history::{
+        db::{insert_db, HistoryRow},
+        store::SqliteStore,
+    };
+
+    // `VACUUM INTO` copies an in-memory database to memory, use a file
+    let dir = Scratch::new("node-monitor-test-restore")?;
+    let options = SqliteConnectOptions::from_str(&dir.0.join("source.db").to_string_lossy())?
+        .create_if_missing(true);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+    super::history::migrate::migrate(&pool).await?;
+    let row = HistoryRow {
+        name: "test".to_string(),
+        miner: "f01".to_string(),
+        timestamp: 1,
+        ..Default::default()
+    };
+    insert_db(pool.clone(), row).await?;
+    let store: SharedStore = Arc::new(SqliteStore { pool });
+
+    let archive = dir.0.join("backup.tar.gz");
+    let manifest = create_backup(&store, fs::File::create(&archive)?).await?;
+    assert_eq!(manifest.files.len(), 5);
+    assert_eq!(manifest.schema_version, latest_version());
+
+    let targets = BackupTargets {
+        history_db: Some(dir.0.join("history.db")),
+        config: dir.0.join("config.json"),
+        nodes: dir.0.join("nodes.json"),
+        history: dir.0.join("history.json"),
+        users: dir.0.join("users.json"),
+    };
+    let report = restore_backup(fs::File::open(&archive)?, &targets).await?;
+    assert_eq!(report.restored.len(), 5);
+    assert!(report.previous.is_empty());
+
+    let restored = SqlitePoolOptions::new()
+        .connect(&targets.history_db.clone().unwrap().to_string_lossy())
+        .await?;
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM history")
+        .fetch_one(&restored)
+        .await?;
+    assert_eq!(rows, 1);
+    restored.close().await;
+
+    // a second restore keeps what it replaced
+    let report = restore_backup(fs::File::open(&archive)?, &targets).await?;
+    assert_eq!(report.previous.len(), 5);
+    assert!(with_suffix(&targets.config, PRE_RESTORE).exists());
+
+    // a cut archive leaves the targets alone
+    let bytes = fs::read(&archive)?;
+    let cut = &bytes[..bytes.len() / 2];
+    assert!(restore_backup(cut, &targets).await.is_err());
+    assert!(!with_suffix(&targets.config, ".restore").exists());
+    check_config_file(&targets.config.to_string_lossy())?;
+
+    // links are refused, not unpacked over the targets
+    let linked = dir.0.join("linked.tar.gz");
+    {
+        let mut tar = tar::Builder::new(GzEncoder::new(
+            fs::File::create(&linked)?,
+            Compression::default(),
+        ));
+        let json = serde_json::to_vec(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, MANIFEST, json.as_slice())?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o644);
+        tar.append_link(&mut header, CONFIG_ENTRY, "/etc/passwd")?;
+        tar.into_inner()?.finish()?;
+    }
+    let e = restore_backup(fs::File::open(&linked)?, &targets)
+        .await
+        .unwrap_err();
+    assert!(e.to_string().contains("not a regular file"), "{}", e);
+    assert!(!fs::symlink_metadata(&targets.config)?.is_symlink());
+
+    Ok(())
+}
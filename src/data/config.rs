@@ -1,62 +1,238 @@
+// the version checks generated by `#[savefile_versions]` trip this lint
+#![allow(clippy::manual_range_contains)]
+
 use std::sync::Arc;
 
 use lazy_static::lazy_static;
-use savefile::{load_file, save_file};
+use savefile::load_file;
 use savefile_derive::Savefile;
-use serde::Serialize;
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::settings::{settings, Settings};
+
+use super::{
+    source::{check_source_names, retry::RetryPolicy},
+    state::{diff_json, is_missing, load_json, load_state, save_json},
+};
 
 lazy_static! {
     pub static ref GLOBAL_CONFIG: Arc<GlobalConfig> = {
-        let config = match GlobalConfig::load_config() {
+        let saved = match load_config() {
             Ok(c) => c,
-            Err(_) => GlobalConfig {
-                timeouts: GlobalTimeouts::default(),
-                interval: RwLock::new(DEFAULT_INTERVAL),
-            },
+            Err(e) => {
+                if !is_missing(&e) {
+                    tracing::error!("{:#}, starting with the default config", e);
+                }
+                Config::default()
+            }
         };
 
-        Arc::new(config)
+        Arc::new(GlobalConfig::new(saved, settings()))
     };
 }
 
 const DEFAULT_TIMEOUT: f32 = 10.;
 const DEFAULT_INTERVAL: f32 = 10.;
-const DEFAULT_CONFIG_FILE: &str = "config.bin";
+const DEFAULT_CONCURRENCY: u32 = 4;
+const DEFAULT_RATE_LIMIT: f32 = 2.;
+const DEFAULT_RATE_BURST: f32 = 4.;
+const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+const DEFAULT_RETRY_BACKOFF: f32 = 0.5;
+const DEFAULT_RETRY_BACKOFF_MAX: f32 = 8.;
+const DEFAULT_BREAKER_THRESHOLD: u32 = 5;
+const DEFAULT_BREAKER_COOLDOWN: f32 = 300.;
+const DEFAULT_SOURCE: &str = "filfox";
+const DEFAULT_LOTUS_API: &str = "http://127.0.0.1:1234/rpc/v0";
+// version of the last config.bin, only read when converting it to json
+const CONFIG_VERSION: u32 = 3;
 lazy_static! {
     pub static ref CONFIG_FILE: String = {
-        option_env!("CONFIG_FILE")
-            .unwrap_or(DEFAULT_CONFIG_FILE)
-            .to_string()
+        let settings = settings();
+        settings.state_path(&settings.config_file)
     };
 }
 
-#[derive(Savefile)]
+// keys missing from config.json take their defaults
+#[derive(Savefile, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
 pub struct Config {
     pub timeouts: Timeouts,
     pub interval: f32,
+    #[savefile_versions = "2.."]
+    #[savefile_default_val = "4"]
+    pub concurrency: u32,
+    #[savefile_versions = "2.."]
+    #[savefile_default_val = "2"]
+    pub rate_limit: f32,
+    #[savefile_versions = "2.."]
+    #[savefile_default_val = "4"]
+    pub rate_burst: f32,
+    #[savefile_versions = "3.."]
+    #[savefile_default_val = "3"]
+    pub retry_attempts: u32,
+    #[savefile_versions = "3.."]
+    #[savefile_default_val = "0.5"]
+    pub retry_backoff: f32,
+    #[savefile_versions = "3.."]
+    #[savefile_default_val = "8"]
+    pub retry_backoff_max: f32,
+    #[savefile_versions = "3.."]
+    #[savefile_default_val = "5"]
+    pub breaker_threshold: u32,
+    #[savefile_versions = "3.."]
+    #[savefile_default_val = "300"]
+    pub breaker_cooldown: f32,
+    // config.bin never had these, they only live in config.json
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    #[savefile_default_fn = "default_sources"]
+    pub sources: Vec<String>,
+    // rpc url of the lotus daemon, its token is a secret kept in the settings
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    #[savefile_default_fn = "default_lotus_api"]
+    pub lotus_api: String,
+}
+
+fn default_sources() -> Vec<String> {
+    vec![DEFAULT_SOURCE.to_string()]
+}
+
+fn default_lotus_api() -> String {
+    DEFAULT_LOTUS_API.to_string()
 }
 
 pub struct GlobalConfig {
     pub timeouts: GlobalTimeouts,
-    // interval between two requests
+    // interval between two requests of the same node
     pub interval: RwLock<f32>,
+    // max nodes fetched at the same time
+    pub concurrency: RwLock<u32>,
+    // requests per second allowed to each upstream host
+    pub rate_limit: RwLock<f32>,
+    // requests allowed in a burst to each upstream host
+    pub rate_burst: RwLock<f32>,
+    // tries per source for timeouts, 429 and 5xx, including the first one
+    pub retry_attempts: RwLock<u32>,
+    // seconds before the first retry, doubled on every retry
+    pub retry_backoff: RwLock<f32>,
+    // upper bound of the retry backoff in seconds
+    pub retry_backoff_max: RwLock<f32>,
+    // failed fetches in a row that open a node's circuit breaker
+    pub breaker_threshold: RwLock<u32>,
+    // seconds an open breaker waits before letting a trial fetch through
+    pub breaker_cooldown: RwLock<f32>,
+    // source order for nodes without their own in nodes.json
+    pub sources: RwLock<Vec<String>>,
+    pub lotus_api: RwLock<String>,
+    // what config.json holds, without the values pinned by the settings
+    saved: RwLock<Config>,
+    // the settings layer the running config was built with
+    settings: Settings,
+    // one change at a time, so a version check holds until the change is saved
+    update_lock: Mutex<()>,
+}
+
+// why a config change was refused, nothing is applied when one is returned
+#[derive(Debug)]
+pub enum ConfigUpdateError {
+    // the config is no longer what the change was based on
+    Stale(String),
+    // out of range, unknown or pinned by the settings
+    Invalid(anyhow::Error),
+    // config.json could not be written
+    Save(anyhow::Error),
+}
+
+impl std::fmt::Display for ConfigUpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigUpdateError::Stale(message) => write!(f, "{}", message),
+            ConfigUpdateError::Invalid(e) => write!(f, "invalid config: {:#}", e),
+            ConfigUpdateError::Save(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigUpdateError {}
+
+impl GlobalConfig {
+    // run `saved`, as read from config.json, with the values in `settings` over it
+    pub fn new(saved: Config, settings: &Settings) -> Self {
+        let mut config = saved.clone();
+        config.apply_settings(settings);
+        let mut global: GlobalConfig = config.into();
+        global.saved = saved.into();
+        global.settings = settings.clone();
+        global
+    }
 }
 
 impl From<Config> for GlobalConfig {
     fn from(config: Config) -> Self {
         Self {
+            saved: config.clone().into(),
+            settings: Settings::default(),
             timeouts: config.timeouts.into(),
             interval: config.interval.into(),
+            concurrency: config.concurrency.into(),
+            rate_limit: config.rate_limit.into(),
+            rate_burst: config.rate_burst.into(),
+            retry_attempts: config.retry_attempts.into(),
+            retry_backoff: config.retry_backoff.into(),
+            retry_backoff_max: config.retry_backoff_max.into(),
+            breaker_threshold: config.breaker_threshold.into(),
+            breaker_cooldown: config.breaker_cooldown.into(),
+            sources: config.sources.into(),
+            lotus_api: config.lotus_api.into(),
+            update_lock: Mutex::new(()),
         }
     }
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeouts: Timeouts::default(),
+            interval: DEFAULT_INTERVAL,
+            concurrency: DEFAULT_CONCURRENCY,
+            rate_limit: DEFAULT_RATE_LIMIT,
+            rate_burst: DEFAULT_RATE_BURST,
+            retry_attempts: DEFAULT_RETRY_ATTEMPTS,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            retry_backoff_max: DEFAULT_RETRY_BACKOFF_MAX,
+            breaker_threshold: DEFAULT_BREAKER_THRESHOLD,
+            breaker_cooldown: DEFAULT_BREAKER_COOLDOWN,
+            sources: default_sources(),
+            lotus_api: default_lotus_api(),
+        }
+    }
+}
+
+impl Default for GlobalConfig {
+    fn default() -> Self {
+        Config::default().into()
+    }
+}
+
 impl GlobalConfig {
-    async fn config(&self) -> Config {
+    pub async fn config(&self) -> Config {
         Config {
             timeouts: self.timeouts.config().await,
             interval: *self.interval.read().await,
+            concurrency: *self.concurrency.read().await,
+            rate_limit: *self.rate_limit.read().await,
+            rate_burst: *self.rate_burst.read().await,
+            retry_attempts: *self.retry_attempts.read().await,
+            retry_backoff: *self.retry_backoff.read().await,
+            retry_backoff_max: *self.retry_backoff_max.read().await,
+            breaker_threshold: *self.breaker_threshold.read().await,
+            breaker_cooldown: *self.breaker_cooldown.read().await,
+            sources: self.sources.read().await.clone(),
+            lotus_api: self.lotus_api.read().await.clone(),
         }
     }
 
@@ -64,26 +240,271 @@ impl GlobalConfig {
         *self.interval.read().await
     }
 
-    pub async fn set_interval(&self, interval: f32) -> anyhow::Result<()> {
-        *self.interval.write().await = interval;
-        Ok(())
+    pub async fn concurrency(&self) -> u32 {
+        *self.concurrency.read().await
+    }
+
+    // (requests per second, burst) allowed to each upstream host
+    pub async fn rate_limit(&self) -> (f32, f32) {
+        (*self.rate_limit.read().await, *self.rate_burst.read().await)
+    }
+
+    pub async fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            attempts: *self.retry_attempts.read().await,
+            backoff: *self.retry_backoff.read().await,
+            backoff_max: *self.retry_backoff_max.read().await,
+        }
+    }
+
+    // (failures in a row to open, seconds to stay open)
+    pub async fn breaker(&self) -> (u32, f32) {
+        (
+            *self.breaker_threshold.read().await,
+            *self.breaker_cooldown.read().await,
+        )
+    }
+
+    pub async fn sources(&self) -> Vec<String> {
+        self.sources.read().await.clone()
+    }
+
+    pub async fn lotus_api(&self) -> String {
+        self.lotus_api.read().await.clone()
+    }
+
+    // the running config with the values pinned by the settings put back to
+    // the saved ones, so flags and env never end up in config.json
+    async fn saved_config(&self) -> Config {
+        let saved = self.saved.read().await;
+        self.config().await.unpinned(&saved, &self.settings)
     }
 
     pub async fn save(&self) -> anyhow::Result<()> {
-        let config: Config = self.config().await;
-        save_config(&config);
+        let config: Config = self.saved_config().await;
+        save_config(&config)?;
+        *self.saved.write().await = config;
         Ok(())
     }
 
-    fn load_config() -> anyhow::Result<GlobalConfig> {
-        let config: GlobalConfig = load_config()?.into();
+    // write the current state somewhere else than the state file, for backups
+    pub async fn save_to(&self, path: &str) -> anyhow::Result<()> {
+        let config: Config = self.saved_config().await;
+        save_json(path, &config)
+    }
+
+    async fn set(&self, config: Config) {
+        *self.timeouts.filfox.write().await = config.timeouts.filfox;
+        *self.timeouts.lotus.write().await = config.timeouts.lotus;
+        *self.interval.write().await = config.interval;
+        *self.concurrency.write().await = config.concurrency;
+        *self.rate_limit.write().await = config.rate_limit;
+        *self.rate_burst.write().await = config.rate_burst;
+        *self.retry_attempts.write().await = config.retry_attempts;
+        *self.retry_backoff.write().await = config.retry_backoff;
+        *self.retry_backoff_max.write().await = config.retry_backoff_max;
+        *self.breaker_threshold.write().await = config.breaker_threshold;
+        *self.breaker_cooldown.write().await = config.breaker_cooldown;
+        *self.sources.write().await = config.sources;
+        *self.lotus_api.write().await = config.lotus_api;
+    }
+
+    // apply `change` to a copy of the running config, then save it and swap it
+    // in if it is valid, `expected` is the version the change was based on
+    pub async fn update<F>(
+        &self,
+        expected: Option<&str>,
+        change: F,
+    ) -> Result<Config, ConfigUpdateError>
+    where
+        F: FnOnce(&mut Config) -> anyhow::Result<()>,
+    {
+        let _lock = self.update_lock.lock().await;
+        let current = self.config().await;
+        if let Some(expected) = expected {
+            let version = current.version();
+            if expected != version {
+                return Err(ConfigUpdateError::Stale(format!(
+                    "config is at version {}, not {}",
+                    version, expected
+                )));
+            }
+        }
+
+        let mut config = current.clone();
+        change(&mut config).map_err(ConfigUpdateError::Invalid)?;
+        config.validate().map_err(ConfigUpdateError::Invalid)?;
+        config
+            .check_pinned(&current, &self.settings)
+            .map_err(ConfigUpdateError::Invalid)?;
+
+        let saved = config.unpinned(&*self.saved.read().await, &self.settings);
+        save_config(&saved).map_err(ConfigUpdateError::Save)?;
+        *self.saved.write().await = saved;
+        self.set(config.clone()).await;
         Ok(config)
     }
 
-    pub fn load(&mut self) -> anyhow::Result<()> {
-        let config: GlobalConfig = load_config()?.into();
-        *self = config;
+    // read config.json again after it was edited, returns what changed, an
+    // invalid file is rejected and the running config left alone
+    pub async fn reload(&self) -> anyhow::Result<Vec<String>> {
+        let _lock = self.update_lock.lock().await;
+        let saved: Config = load_json(&CONFIG_FILE)?;
+        let mut config = saved.clone();
+        config.apply_settings(&self.settings);
+        config.validate()?;
+
+        *self.saved.write().await = saved;
+        let changes = diff_json(&self.config().await, &config)?;
+        if !changes.is_empty() {
+            self.set(config).await;
+        }
+        Ok(changes)
+    }
+}
+
+impl Config {
+    // sha256 of the json, the same across restarts and builds for the same
+    // values, sent as the etag of the config api
+    pub fn version(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        format!("{:x}", Sha256::digest(json.as_bytes()))
+    }
 
+    // apply a json merge patch, unknown keys and wrong types are errors
+    pub fn patch(&mut self, patch: &Value) -> anyhow::Result<()> {
+        let mut value = serde_json::to_value(&*self)?;
+        merge_patch(&mut value, patch, "")?;
+        *self = serde_json::from_value(value)?;
+        Ok(())
+    }
+
+    // values set in the settings would come back on the next reload or start
+    fn check_pinned(&self, current: &Config, settings: &Settings) -> anyhow::Result<()> {
+        let pinned = [
+            (
+                "timeouts.filfox",
+                settings.timeouts.filfox.is_some(),
+                self.timeouts.filfox != current.timeouts.filfox,
+            ),
+            (
+                "timeouts.lotus",
+                settings.timeouts.lotus.is_some(),
+                self.timeouts.lotus != current.timeouts.lotus,
+            ),
+            (
+                "interval",
+                settings.interval.is_some(),
+                self.interval != current.interval,
+            ),
+            (
+                "sources",
+                !settings.sources.is_empty(),
+                self.sources != current.sources,
+            ),
+            (
+                "lotus_api",
+                settings.lotus.api.is_some(),
+                self.lotus_api != current.lotus_api,
+            ),
+        ];
+        for (name, set, changed) in pinned {
+            if set && changed {
+                anyhow::bail!("{} is set in the settings, change it there", name);
+            }
+        }
+        Ok(())
+    }
+
+    // this config with the fields `settings` pins taken from `saved` instead
+    fn unpinned(&self, saved: &Config, settings: &Settings) -> Config {
+        let mut config = self.clone();
+        if settings.timeouts.filfox.is_some() {
+            config.timeouts.filfox = saved.timeouts.filfox;
+        }
+        if settings.timeouts.lotus.is_some() {
+            config.timeouts.lotus = saved.timeouts.lotus;
+        }
+        if settings.interval.is_some() {
+            config.interval = saved.interval;
+        }
+        if !settings.sources.is_empty() {
+            config.sources = saved.sources.clone();
+        }
+        if settings.lotus.api.is_some() {
+            config.lotus_api = saved.lotus_api.clone();
+        }
+        config
+    }
+
+    // values given in the settings win over the saved ones
+    fn apply_settings(&mut self, settings: &Settings) {
+        if let Some(filfox) = settings.timeouts.filfox {
+            self.timeouts.filfox = filfox;
+        }
+        if let Some(lotus) = settings.timeouts.lotus {
+            self.timeouts.lotus = lotus;
+        }
+        if let Some(interval) = settings.interval {
+            self.interval = interval;
+        }
+        if !settings.sources.is_empty() {
+            self.sources = settings.sources.clone();
+        }
+        if let Some(api) = &settings.lotus.api {
+            self.lotus_api = api.clone();
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let seconds = [
+            ("timeouts.filfox", self.timeouts.filfox),
+            ("timeouts.lotus", self.timeouts.lotus),
+            ("interval", self.interval),
+            ("rate_limit", self.rate_limit),
+        ];
+        for (name, value) in seconds {
+            if !(value.is_finite() && value > 0.) {
+                anyhow::bail!("{} must be above 0, got {}", name, value);
+            }
+        }
+        let non_negative = [
+            ("retry_backoff", self.retry_backoff),
+            ("retry_backoff_max", self.retry_backoff_max),
+            ("breaker_cooldown", self.breaker_cooldown),
+        ];
+        for (name, value) in non_negative {
+            if !(value.is_finite() && value >= 0.) {
+                anyhow::bail!("{} must not be negative, got {}", name, value);
+            }
+        }
+        let counts = [
+            ("concurrency", self.concurrency),
+            ("retry_attempts", self.retry_attempts),
+            ("breaker_threshold", self.breaker_threshold),
+        ];
+        for (name, value) in counts {
+            if value < 1 {
+                anyhow::bail!("{} must be at least 1, got {}", name, value);
+            }
+        }
+        if !(self.rate_burst.is_finite() && self.rate_burst >= 1.) {
+            anyhow::bail!("rate_burst must be at least 1, got {}", self.rate_burst);
+        }
+        if self.retry_backoff_max < self.retry_backoff {
+            anyhow::bail!(
+                "retry_backoff_max {} is below retry_backoff {}",
+                self.retry_backoff_max,
+                self.retry_backoff
+            );
+        }
+        if self.sources.is_empty() {
+            anyhow::bail!("sources must name at least one source");
+        }
+        check_source_names(&self.sources)?;
+        if reqwest::Url::parse(&self.lotus_api).is_err() {
+            anyhow::bail!("lotus_api {} is not a url", self.lotus_api);
+        }
         Ok(())
     }
 }
@@ -91,40 +512,60 @@ impl GlobalConfig {
 pub struct GlobalTimeouts {
     /// filfox timeout
     pub filfox: RwLock<f32>,
+    /// lotus rpc timeout
+    pub lotus: RwLock<f32>,
 }
 
-#[derive(Serialize, Savefile)]
+#[derive(Serialize, Deserialize, Savefile, Clone, Debug, PartialEq)]
+#[serde(default)]
 pub struct Timeouts {
     /// filfox timeout
     pub filfox: f32,
+    /// lotus rpc timeout
+    #[savefile_versions = "1.."]
+    #[savefile_default_val = "10"]
+    pub lotus: f32,
 }
 
 impl GlobalTimeouts {
     pub async fn filfox(&self) -> f32 {
         *self.filfox.read().await
     }
+
+    pub async fn lotus(&self) -> f32 {
+        *self.lotus.read().await
+    }
 }
 
 impl GlobalTimeouts {
     async fn config(&self) -> Timeouts {
         Timeouts {
             filfox: *self.filfox.read().await,
+            lotus: *self.lotus.read().await,
         }
     }
 }
 
-impl Default for GlobalTimeouts {
+impl Default for Timeouts {
     fn default() -> Self {
         Self {
-            filfox: RwLock::new(DEFAULT_TIMEOUT),
+            filfox: DEFAULT_TIMEOUT,
+            lotus: DEFAULT_TIMEOUT,
         }
     }
 }
 
+impl Default for GlobalTimeouts {
+    fn default() -> Self {
+        Timeouts::default().into()
+    }
+}
+
 impl From<Timeouts> for GlobalTimeouts {
     fn from(t: Timeouts) -> Self {
         Self {
             filfox: RwLock::new(t.filfox),
+            lotus: RwLock::new(t.lotus),
         }
     }
 }
@@ -133,14 +574,128 @@ impl From<GlobalTimeouts> for Timeouts {
     fn from(t: GlobalTimeouts) -> Self {
         Self {
             filfox: *t.filfox.blocking_read(),
+            lotus: *t.lotus.blocking_read(),
         }
     }
 }
 
-fn save_config(config: &Config) {
-    save_file(&*CONFIG_FILE, 0, config).unwrap();
+fn merge_patch(target: &mut Value, patch: &Value, path: &str) -> anyhow::Result<()> {
+    let (target, patch) = match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => (target, patch),
+        _ => anyhow::bail!(
+            "{} must be an object",
+            if path.is_empty() { "config" } else { path }
+        ),
+    };
+    for (key, value) in patch {
+        let path = match path {
+            "" => key.clone(),
+            _ => format!("{}.{}", path, key),
+        };
+        match target.get_mut(key) {
+            Some(field) if field.is_object() => merge_patch(field, value, &path)?,
+            Some(field) => *field = value.clone(),
+            None => anyhow::bail!("unknown field {}", path),
+        }
+    }
+    Ok(())
+}
+
+fn save_config(config: &Config) -> anyhow::Result<()> {
+    save_json(&CONFIG_FILE, config)
 }
 
 fn load_config() -> anyhow::Result<Config> {
-    Ok(load_file(&*CONFIG_FILE, 0)?)
+    load_state(&CONFIG_FILE, load_legacy_config)
+}
+
+// config.bin written by older builds
+pub fn load_legacy_config(path: &str) -> anyhow::Result<Config> {
+    Ok(load_file(path, CONFIG_VERSION)?)
+}
+
+// fails when `path` is not a config.json this build can load
+pub fn check_config_file(path: &str) -> anyhow::Result<()> {
+    let _: Config = load_json(path)?;
+    Ok(())
+}
+
+#[test]
+fn test_config_patch() -> anyhow::Result<()> {
+    let mut config = Config::default();
+    let version = config.version();
+    assert_eq!(version, Config::default().version());
+    assert_eq!(version.len(), 64);
+
+    config.patch(&serde_json::json!({"interval": 30, "timeouts": {"lotus": 5}}))?;
+    assert_eq!(config.interval, 30.);
+    assert_eq!(config.timeouts.lotus, 5.);
+    assert_eq!(config.timeouts.filfox, DEFAULT_TIMEOUT);
+    assert_ne!(config.version(), version);
+    config.validate()?;
+
+    let mut bad = config.clone();
+    assert!(bad.patch(&serde_json::json!({"intervall": 30})).is_err());
+    assert!(bad.patch(&serde_json::json!({"interval": "30"})).is_err());
+    assert!(bad.patch(&serde_json::json!({"timeouts": 5})).is_err());
+    assert_eq!(bad, config);
+
+    for patch in [
+        serde_json::json!({"interval": 0}),
+        serde_json::json!({"interval": -1}),
+        serde_json::json!({"concurrency": 0}),
+        serde_json::json!({"retry_backoff": 10, "retry_backoff_max": 1}),
+        serde_json::json!({"sources": []}),
+        serde_json::json!({"sources": ["lotus", "etherscan"]}),
+        serde_json::json!({"lotus_api": "localhost"}),
+    ] {
+        let mut c = config.clone();
+        c.patch(&patch)?;
+        assert!(c.validate().is_err(), "{}", patch);
+    }
+
+    // a value pinned by the settings cannot be changed here
+    let settings = Settings {
+        interval: Some(30.),
+        ..Default::default()
+    };
+    let mut c = config.clone();
+    c.concurrency = 8;
+    c.check_pinned(&config, &settings)?;
+    c.interval = 60.;
+    assert!(c.check_pinned(&config, &settings).is_err());
+
+    // sources and the lotus daemon are config too, older files get the defaults
+    let old: Config = serde_json::from_str(r#"{"interval": 30}"#)?;
+    assert_eq!(old.sources, vec!["filfox"]);
+    assert_eq!(old.lotus_api, DEFAULT_LOTUS_API);
+    c = config.clone();
+    c.patch(&serde_json::json!({"sources": ["lotus", "filfox"], "lotus_api": "http://lotus:1234/rpc/v0"}))?;
+    c.validate()?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pinned_not_saved() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("node-monitor-test-pinned-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("config.json").to_string_lossy().to_string();
+
+    let settings = Settings {
+        interval: Some(5.),
+        ..Default::default()
+    };
+    let config = GlobalConfig::new(Config::default(), &settings);
+    assert_eq!(config.interval().await, 5.);
+
+    // an unrelated change, the flag's interval stays out of the file
+    *config.concurrency.write().await = 8;
+    config.save_to(&path).await?;
+    let saved: Config = load_json(&path)?;
+    assert_eq!(saved.interval, DEFAULT_INTERVAL);
+    assert_eq!(saved.concurrency, 8);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
 }
@@ -1,21 +1,66 @@
-use crate::data::config::GLOBAL_CONFIG;
+use async_trait::async_trait;
+
+use crate::data::{
+    config::GLOBAL_CONFIG,
+    source::{url_host, MinerDataSource},
+};
 
 use super::models::FilfoxMinerInfo;
 
 const FILFOX_MINER_URL: &str = "https://filfox.info/api/v1/address/";
 
-pub async fn download_from_downloadinfo(id: &str) -> anyhow::Result<FilfoxMinerInfo> {
-    let url = format!("{}{}", FILFOX_MINER_URL, id);
+// miner data from the filfox explorer api
+pub struct FilfoxSource {
+    pub base_url: String,
+}
+
+impl FilfoxSource {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+        }
+    }
+}
+
+impl Default for FilfoxSource {
+    fn default() -> Self {
+        Self::new(FILFOX_MINER_URL)
+    }
+}
 
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs_f32(
-            GLOBAL_CONFIG.timeouts.filfox().await,
-        ))
-        .build()?;
+#[async_trait]
+impl MinerDataSource for FilfoxSource {
+    fn name(&self) -> &str {
+        "filfox"
+    }
 
-    let res: FilfoxMinerInfo = client.get(url).send().await?.json().await?;
+    fn host(&self) -> String {
+        url_host(&self.base_url)
+    }
 
-    Ok(res)
+    async fn miner_info(&self, id: &str) -> anyhow::Result<FilfoxMinerInfo> {
+        let url = format!("{}{}", self.base_url, id);
+
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs_f32(
+                GLOBAL_CONFIG.timeouts.filfox().await,
+            ))
+            .build()?;
+
+        let res: FilfoxMinerInfo = client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(res)
+    }
+}
+
+pub async fn download_from_downloadinfo(id: &str) -> anyhow::Result<FilfoxMinerInfo> {
+    FilfoxSource::default().miner_info(id).await
 }
 
 #[tokio::test]
@@ -1,17 +1,58 @@
-use chrono::{DateTime, Local, SecondsFormat};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-use crate::data::history::db::{DealDbType, DealDbTypeFull};
+use crate::data::history::db::HistoryRow;
+
+const ATTO: f64 = 1.0e18;
+const TIB: f64 = 1024. * 1024. * 1024. * 1024.;
+
+// FIL from an attoFIL string
+fn fil(atto: &str) -> f64 {
+    atto.parse::<f64>().unwrap_or(0.) / ATTO
+}
+
+// TiB from a byte string
+fn tib(bytes: &str) -> f64 {
+    bytes.parse::<f64>().unwrap_or(0.) / TIB
+}
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MinerInfo {
     pub id: String,
     pub pledge: f64,
+    /// quality adjusted power in TiB
     pub power: f64,
     pub blocks: u64,
     pub rewards: f64,
+    /// raw byte power in TiB
+    pub raw_power: f64,
+    pub balance: f64,
+    pub available_balance: f64,
+    pub vesting_funds: f64,
+    pub pre_commit_deposits: f64,
+    pub sectors_active: i64,
+    pub sectors_faulty: i64,
+    pub sectors_live: i64,
+    pub sectors_recovering: i64,
+    /// network rank by quality adjusted power, 0 for totals
+    pub power_rank: i64,
+    /// network rank by raw byte power, 0 for totals
+    pub raw_power_rank: i64,
+    /// source the data was fetched from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// time of the last successful fetch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched_at: Option<i64>,
+    /// the latest fetch failed and the values are last-known-good
+    #[serde(default)]
+    pub stale: bool,
+    /// why the latest fetch failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 impl MinerInfo {
@@ -22,8 +63,40 @@ impl MinerInfo {
             power: 0.,
             blocks: 0,
             rewards: 0.,
+            raw_power: 0.,
+            balance: 0.,
+            available_balance: 0.,
+            vesting_funds: 0.,
+            pre_commit_deposits: 0.,
+            sectors_active: 0,
+            sectors_faulty: 0,
+            sectors_live: 0,
+            sectors_recovering: 0,
+            power_rank: 0,
+            raw_power_rank: 0,
+            source: None,
+            fetched_at: None,
+            stale: false,
+            error: None,
         }
     }
+
+    // add the summable metrics of `other` to this total
+    pub fn add(&mut self, other: &MinerInfo) {
+        self.pledge += other.pledge;
+        self.power += other.power;
+        self.blocks += other.blocks;
+        self.rewards += other.rewards;
+        self.raw_power += other.raw_power;
+        self.balance += other.balance;
+        self.available_balance += other.available_balance;
+        self.vesting_funds += other.vesting_funds;
+        self.pre_commit_deposits += other.pre_commit_deposits;
+        self.sectors_active += other.sectors_active;
+        self.sectors_faulty += other.sectors_faulty;
+        self.sectors_live += other.sectors_live;
+        self.sectors_recovering += other.sectors_recovering;
+    }
 }
 
 impl Default for MinerInfo {
@@ -32,62 +105,112 @@ impl Default for MinerInfo {
     }
 }
 
-// String, // 0    name
-// i64,    // 1    timestamp
-// f32,    // 2    pledge
-// f32,    // 3    power
-// i64,    // 4    blocks
-// f32,    // 5    rewards
-impl From<DealDbType> for MinerInfo {
-    fn from(value: DealDbType) -> Self {
+impl From<HistoryRow> for MinerInfo {
+    fn from(value: HistoryRow) -> Self {
         Self {
-            id: "all".to_string(),
-            pledge: value.2,
-            power: value.3,
-            blocks: value.4 as u64,
-            rewards: value.5,
+            id: value.miner,
+            pledge: value.pledge,
+            power: value.power,
+            blocks: value.blocks as u64,
+            rewards: value.rewards,
+            raw_power: value.raw_power,
+            balance: value.balance,
+            available_balance: value.available_balance,
+            vesting_funds: value.vesting_funds,
+            pre_commit_deposits: value.pre_commit_deposits,
+            sectors_active: value.sectors_active,
+            sectors_faulty: value.sectors_faulty,
+            sectors_live: value.sectors_live,
+            sectors_recovering: value.sectors_recovering,
+            power_rank: value.power_rank,
+            raw_power_rank: value.raw_power_rank,
+            ..Self::new()
         }
     }
 }
 
-impl From<DealDbTypeFull> for MinerInfo {
-    fn from(value: DealDbTypeFull) -> Self {
+impl From<FilfoxMinerInfo> for MinerInfo {
+    fn from(value: FilfoxMinerInfo) -> Self {
+        let miner = value.miner;
+
         Self {
-            id: "all".to_string(),
-            pledge: value.3,
-            power: value.4,
-            blocks: value.5 as u64,
-            rewards: value.6,
+            id: value.id,
+            pledge: fil(&miner.initial_pledge_requirement),
+            power: tib(&miner.quality_adj_power),
+            blocks: miner.weighted_blocks_mined as u64,
+            rewards: fil(&miner.total_rewards),
+            raw_power: tib(&miner.raw_byte_power),
+            balance: fil(&value.balance),
+            available_balance: fil(&miner.available_balance),
+            vesting_funds: fil(&miner.vesting_funds),
+            pre_commit_deposits: fil(&miner.pre_commit_deposits),
+            sectors_active: miner.sectors.active,
+            sectors_faulty: miner.sectors.faulty,
+            sectors_live: miner.sectors.live,
+            sectors_recovering: miner.sectors.recovering,
+            power_rank: miner.quality_adj_power_rank,
+            raw_power_rank: miner.raw_byte_power_rank,
+            ..Self::new()
         }
     }
 }
 
-impl From<FilfoxMinerInfo> for MinerInfo {
-    fn from(value: FilfoxMinerInfo) -> Self {
-        let pledge: f64 = value.miner.initial_pledge_requirement.parse().unwrap_or(0.);
-        let pledge = pledge / (1.0e18);
-
-        let rewards = value.miner.total_rewards.parse().unwrap_or(0.);
-        let rewards = rewards / (1.0e18);
+impl From<MinerRecord> for MinerInfo {
+    fn from(value: MinerRecord) -> Self {
+        Self {
+            source: value.source,
+            fetched_at: value.fetched_at,
+            stale: value.stale,
+            error: value.error,
+            ..MinerInfo::from(value.info)
+        }
+    }
+}
 
-        let power = value.miner.quality_adj_power.parse().unwrap_or(0.);
-        let power = power / 1024. / 1024. / 1024. / 1024.;
+// last-known-good miner data of a node and how fresh it is
+#[derive(Debug, Clone)]
+pub struct MinerRecord {
+    // node as subscribed, may differ from `info.id` for robust addresses
+    pub node: String,
+    pub info: FilfoxMinerInfo,
+    pub source: Option<String>,
+    pub fetched_at: Option<i64>,
+    pub stale: bool,
+    pub error: Option<String>,
+}
 
-        let blocks = value.miner.weighted_blocks_mined;
+impl MinerRecord {
+    pub fn fresh(node: &str, info: FilfoxMinerInfo, source: &str) -> Self {
+        Self {
+            node: node.to_string(),
+            info,
+            source: Some(source.to_string()),
+            fetched_at: Some(Utc::now().timestamp()),
+            stale: false,
+            error: None,
+        }
+    }
 
+    // placeholder for a node that has never been fetched successfully
+    pub fn missing(id: &str, error: String) -> Self {
         Self {
-            id: value.id,
-            pledge,
-            power,
-            blocks: blocks as u64,
-            rewards,
+            node: id.to_string(),
+            info: FilfoxMinerInfo {
+                id: id.to_string(),
+                ..Default::default()
+            },
+            source: None,
+            fetched_at: None,
+            stale: true,
+            error: Some(error),
         }
     }
 }
 
 pub struct MinerInfos {
     pub last_update: RwLock<DateTime<Local>>,
-    pub infos: RwLock<Vec<FilfoxMinerInfo>>,
+    // one record per node, in subscription order
+    pub infos: RwLock<Vec<MinerRecord>>,
 }
 
 impl MinerInfos {
@@ -104,6 +227,34 @@ impl MinerInfos {
             .await
             .to_rfc3339_opts(SecondsFormat::Millis, false))
     }
+
+    // apply the result of fetching `id`, a failure keeps the previous data marked stale
+    pub async fn update_node(&self, id: &str, res: anyhow::Result<MinerRecord>) {
+        let mut infos = self.infos.write().await;
+        let idx = infos.iter().position(|r| r.node == id);
+
+        let record = match (res, idx) {
+            (Ok(record), _) => record,
+            (Err(e), Some(idx)) => MinerRecord {
+                stale: true,
+                error: Some(e.to_string()),
+                ..infos[idx].clone()
+            },
+            (Err(e), None) => MinerRecord::missing(id, e.to_string()),
+        };
+
+        match idx {
+            Some(idx) => infos[idx] = record,
+            None => infos.push(record),
+        }
+    }
+
+    // drop nodes no longer subscribed and keep the rest in `nodes` order
+    pub async fn retain(&self, nodes: &[String]) {
+        let mut infos = self.infos.write().await;
+        infos.retain(|r| nodes.contains(&r.node));
+        infos.sort_by_key(|r| nodes.iter().position(|n| *n == r.node));
+    }
 }
 
 impl Default for MinerInfos {
@@ -121,8 +272,8 @@ impl MinerInfos {
     }
 }
 
-impl From<Vec<FilfoxMinerInfo>> for MinerInfos {
-    fn from(value: Vec<FilfoxMinerInfo>) -> Self {
+impl From<Vec<MinerRecord>> for MinerInfos {
+    fn from(value: Vec<MinerRecord>) -> Self {
         Self {
             last_update: RwLock::new(Local::now()),
             infos: RwLock::new(value),
@@ -134,7 +285,7 @@ lazy_static! {
     pub static ref GLOBAL_MINER_INFOS: MinerInfos = MinerInfos::new();
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct FilfoxMinerInfo {
     pub actor: String,
     pub address: String,
@@ -159,7 +310,7 @@ pub struct FilfoxMinerInfo {
     pub worker_miners: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Miner {
     #[serde(rename = "availableBalance")]
     pub available_balance: String,
@@ -204,19 +355,19 @@ pub struct Miner {
     pub worker: Worker,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ControlAddress {
     pub address: String,
     pub balance: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Owner {
     pub address: String,
     pub balance: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Sectors {
     pub active: i64,
     pub faulty: i64,
@@ -224,8 +375,42 @@ pub struct Sectors {
     pub recovering: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Worker {
     pub address: String,
     pub balance: String,
 }
+
+#[tokio::test]
+async fn test_miner_infos_keep_last_known_good() -> anyhow::Result<()> {
+    let infos = MinerInfos::new();
+    let mut info = FilfoxMinerInfo {
+        id: "f01".to_string(),
+        ..Default::default()
+    };
+    info.miner.quality_adj_power = 1024_u64.pow(4).to_string();
+
+    infos
+        .update_node("f01", Ok(MinerRecord::fresh("f01", info, "filfox")))
+        .await;
+    infos
+        .update_node("f01", Err(anyhow::anyhow!("timeout")))
+        .await;
+    infos
+        .update_node("f02", Err(anyhow::anyhow!("timeout")))
+        .await;
+
+    let out = infos.info().await?;
+    assert_eq!(out.len(), 2);
+    assert_eq!(out[0].power, 1.);
+    assert!(out[0].stale);
+    assert!(out[0].fetched_at.is_some());
+    assert_eq!(out[0].error.as_deref(), Some("timeout"));
+    assert!(out[1].stale);
+    assert!(out[1].fetched_at.is_none());
+
+    infos.retain(&["f02".to_string()]).await;
+    assert_eq!(infos.info().await?.len(), 1);
+
+    Ok(())
+}
@@ -1,50 +1,258 @@
-use chrono::Local;
-use sqlx::SqlitePool;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::data::{config::GLOBAL_CONFIG, history::update::update_history, nodes::GLOBAL_NODES};
+use chrono::Local;
+use tokio::sync::{Mutex, Semaphore};
 
-use super::{miner_info::download_from_downloadinfo, models::GLOBAL_MINER_INFOS};
+use crate::data::{
+    config::GLOBAL_CONFIG,
+    history::{retention::compaction_updater, store::SharedStore, update::history_updater},
+    nodes::GLOBAL_NODES,
+    source::{breaker::GLOBAL_BREAKERS, SourcePreferences, SourceRegistry},
+};
 
-pub async fn update_miner_info(conn: SqlitePool) -> anyhow::Result<()> {
-    let nodes = GLOBAL_NODES.nodes().await.nodes;
+use super::models::{MinerInfos, GLOBAL_MINER_INFOS};
 
-    let interval = { *GLOBAL_CONFIG.interval.read().await };
+// longest the scheduler sleeps before looking at the node list again
+const SCHEDULER_TICK: Duration = Duration::from_secs(1);
 
-    if nodes.is_empty() {
-        tokio::time::sleep(std::time::Duration::from_secs_f32(interval)).await;
-    }
+// when each node is next due to be fetched
+#[derive(Default)]
+pub struct NodeSchedule {
+    next_due: HashMap<String, Instant>,
+}
 
-    tracing::info!("polling miner info with interval: {}", interval);
+impl NodeSchedule {
+    // track `nodes`, new nodes are spread over one interval so they don't start at once
+    pub fn sync(&mut self, nodes: &[String], interval: Duration, now: Instant) {
+        self.next_due.retain(|node, _| nodes.contains(node));
 
-    let gap = interval / nodes.len() as f32;
-    let mut infos = vec![];
+        let new: Vec<&String> = nodes
+            .iter()
+            .filter(|n| !self.next_due.contains_key(*n))
+            .collect();
+        let gap = interval / nodes.len().max(1) as u32;
+        for (i, node) in new.into_iter().enumerate() {
+            self.next_due.insert(node.clone(), now + gap * i as u32);
+        }
+    }
 
-    for node in nodes {
-        tokio::time::sleep(std::time::Duration::from_secs_f32(gap)).await;
+    // nodes due at `now`, each is rescheduled one interval later
+    pub fn take_due(&mut self, interval: Duration, now: Instant) -> Vec<String> {
+        let mut due = vec![];
+        for (node, next) in self.next_due.iter_mut() {
+            if *next <= now {
+                due.push(node.clone());
+                *next = now + interval;
+            }
+        }
+        due.sort();
+        due
+    }
 
-        if let Ok(info) = download_from_downloadinfo(&node).await {
-            infos.push(info);
-        };
+    pub fn next_wakeup(&self) -> Option<Instant> {
+        self.next_due.values().min().copied()
     }
-    {
-        *GLOBAL_MINER_INFOS.infos.write().await = infos;
+}
+
+// fetch one node through `registry` into `infos` once a permit is free,
+// unless the node's circuit breaker is open
+pub async fn poll_node(
+    infos: &MinerInfos,
+    registry: &SourceRegistry,
+    node: &str,
+    preferred: Option<&Vec<String>>,
+    permits: &Semaphore,
+) {
+    if !GLOBAL_BREAKERS.allow(node).await {
+        tracing::debug!("circuit breaker of {} is open, skipping", node);
+        return;
     }
 
-    let current_time = Local::now();
-    {
-        *GLOBAL_MINER_INFOS.last_update.write().await = current_time;
+    let _permit = match permits.acquire().await {
+        Ok(p) => p,
+        Err(_) => return,
+    };
+
+    let res = registry.fetch(node, preferred).await;
+    match &res {
+        Ok(_) => GLOBAL_BREAKERS.record_success(node).await,
+        Err(e) => {
+            tracing::error!("{}", e);
+            let (threshold, cooldown) = GLOBAL_CONFIG.breaker().await;
+            GLOBAL_BREAKERS
+                .record_failure(node, e.to_string(), threshold, cooldown)
+                .await;
+        }
     }
+    infos.update_node(node, res).await;
+}
 
-    // spawn db insert check
-    tokio::spawn(async move { update_history(conn).await });
+// grow or shrink `permits` from `current` to `target` permits
+fn resize_permits(permits: &Arc<Semaphore>, current: u32, target: u32) {
+    if target > current {
+        permits.add_permits((target - current) as usize);
+    } else if target < current {
+        let permits = permits.clone();
+        tokio::spawn(async move {
+            if let Ok(p) = permits.acquire_many(current - target).await {
+                p.forget();
+            }
+        });
+    }
+}
 
-    Ok(())
+pub async fn miner_info_updater(store: SharedStore) {
+    miner_info_updater_with(store, Arc::new(SourceRegistry::from_config().await)).await
 }
 
-pub async fn miner_info_updater(conn: SqlitePool) {
+// fetch every node on its own schedule, at most `concurrency` at a time
+pub async fn miner_info_updater_with(store: SharedStore, registry: Arc<SourceRegistry>) {
+    let compact_store = store.clone();
+    tokio::spawn(async move { compaction_updater(compact_store).await });
+    tokio::spawn(async move { history_updater(store).await });
+
+    let mut concurrency = GLOBAL_CONFIG.concurrency().await.max(1);
+    let permits = Arc::new(Semaphore::new(concurrency as usize));
+    let in_flight: Arc<Mutex<HashSet<String>>> = Arc::default();
+    let mut schedule = NodeSchedule::default();
+
     loop {
-        if let Err(e) = update_miner_info(conn.clone()).await {
-            tracing::error!("miner_info_updater error: {}", e)
+        let nodes = GLOBAL_NODES.polled().await;
+        registry.sync().await;
+        let interval = Duration::from_secs_f32(GLOBAL_CONFIG.interval().await.max(0.1));
+
+        let target = GLOBAL_CONFIG.concurrency().await.max(1);
+        if target != concurrency {
+            tracing::info!("polling concurrency: {} -> {}", concurrency, target);
+            resize_permits(&permits, concurrency, target);
+            concurrency = target;
+        }
+
+        GLOBAL_MINER_INFOS.retain(&nodes.nodes).await;
+        GLOBAL_BREAKERS.retain(&nodes.nodes).await;
+        let now = Instant::now();
+        schedule.sync(&nodes.nodes, interval, now);
+
+        let preferences: Arc<SourcePreferences> = Arc::new(nodes.sources);
+        for node in schedule.take_due(interval, now) {
+            // a slow node is not fetched again until its last fetch finished
+            if !in_flight.lock().await.insert(node.clone()) {
+                continue;
+            }
+
+            let registry = registry.clone();
+            let permits = permits.clone();
+            let in_flight = in_flight.clone();
+            let preferences = preferences.clone();
+            tokio::spawn(async move {
+                poll_node(
+                    &GLOBAL_MINER_INFOS,
+                    &registry,
+                    &node,
+                    preferences.get(&node),
+                    &permits,
+                )
+                .await;
+                {
+                    *GLOBAL_MINER_INFOS.last_update.write().await = Local::now();
+                }
+                in_flight.lock().await.remove(&node);
+            });
         }
+
+        let wakeup = schedule
+            .next_wakeup()
+            .unwrap_or(now + SCHEDULER_TICK)
+            .min(now + SCHEDULER_TICK);
+        tokio::time::sleep_until(wakeup.into()).await;
     }
 }
+
+#[test]
+fn test_node_schedule() {
+    let now = Instant::now();
+    let interval = Duration::from_secs(3);
+    let nodes = vec!["f01".to_string(), "f02".to_string(), "f03".to_string()];
+
+    let mut schedule = NodeSchedule::default();
+    schedule.sync(&nodes, interval, now);
+
+    // staggered over the interval
+    assert_eq!(schedule.take_due(interval, now), vec!["f01"]);
+    let due = schedule.take_due(interval, now + Duration::from_secs(2));
+    assert_eq!(due, vec!["f02", "f03"]);
+    assert!(schedule
+        .take_due(interval, now + Duration::from_secs(2))
+        .is_empty());
+
+    // each node keeps its own schedule
+    assert_eq!(
+        schedule.take_due(interval, now + Duration::from_secs(3)),
+        vec!["f01"]
+    );
+
+    schedule.sync(&nodes[1..], interval, now);
+    assert_eq!(schedule.next_due.len(), 2);
+}
+
+#[cfg(test)]
+struct SlowSource {
+    running: std::sync::atomic::AtomicUsize,
+    max_running: std::sync::atomic::AtomicUsize,
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl crate::data::source::MinerDataSource for SlowSource {
+    fn name(&self) -> &str {
+        "slow"
+    }
+
+    async fn miner_info(&self, id: &str) -> anyhow::Result<super::models::FilfoxMinerInfo> {
+        use std::sync::atomic::Ordering;
+
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_running.fetch_max(running, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+
+        Ok(super::models::FilfoxMinerInfo {
+            id: id.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_poll_node_concurrency() -> anyhow::Result<()> {
+    use std::sync::atomic::Ordering;
+
+    let source = Arc::new(SlowSource {
+        running: Default::default(),
+        max_running: Default::default(),
+    });
+    let registry = Arc::new(SourceRegistry::new(vec![source.clone()]));
+    let infos = Arc::new(MinerInfos::new());
+    let permits = Arc::new(Semaphore::new(2));
+
+    let mut tasks = vec![];
+    for i in 0..6 {
+        let (registry, infos, permits) = (registry.clone(), infos.clone(), permits.clone());
+        tasks.push(tokio::spawn(async move {
+            let node = format!("f0{}", i);
+            poll_node(&infos, &registry, &node, None, &permits).await;
+        }));
+    }
+    for t in tasks {
+        t.await?;
+    }
+
+    assert_eq!(infos.info().await?.len(), 6);
+    assert!(source.max_running.load(Ordering::SeqCst) <= 2);
+
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::data::filfox::models::MinerInfo;
+
+use super::db::{HistoryRow, METRIC_COLUMNS};
+
+// weeks start on monday, 1969-12-29 was the last one before the epoch
+const WEEK_OFFSET: i64 = -3 * 86400;
+
+// columns stored as integers, averages are rounded back to integers
+pub(crate) const INTEGER_COLUMNS: &[&str] = &[
+    "blocks",
+    "sectors_active",
+    "sectors_faulty",
+    "sectors_live",
+    "sectors_recovering",
+    "power_rank",
+    "raw_power_rank",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    Minute,
+    Hour,
+    Day,
+    Week,
+}
+
+impl Bucket {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Bucket::Minute => 60,
+            Bucket::Hour => 3600,
+            Bucket::Day => 86400,
+            Bucket::Week => 7 * 86400,
+        }
+    }
+
+    fn offset(&self) -> i64 {
+        match self {
+            Bucket::Week => WEEK_OFFSET,
+            _ => 0,
+        }
+    }
+
+    // sql expression of the bucket start of `timestamp`
+    fn sql(&self) -> String {
+        format!(
+            "((timestamp - ({offset})) / {size}) * {size} + ({offset})",
+            size = self.seconds(),
+            offset = self.offset()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    // the latest sample in the bucket
+    Last,
+    Avg,
+    Min,
+    Max,
+    // change since the end of the previous bucket, or since the first
+    // sample of the bucket when there is no previous one
+    Delta,
+}
+
+// `source` is a table or view with the `history` columns and `filter` its where clause
+fn aggregate_sql(source: &str, filter: &str, bucket: Bucket, aggregate: Aggregate) -> String {
+    let src = format!(
+        "SELECT name, miner, timestamp, {columns}, {bucket} AS bucket FROM {source} WHERE {filter}",
+        columns = METRIC_COLUMNS.join(", "),
+        bucket = bucket.sql(),
+    );
+    let ranked = "SELECT *,
+        ROW_NUMBER() OVER (PARTITION BY bucket, miner ORDER BY timestamp DESC) AS rn_last,
+        ROW_NUMBER() OVER (PARTITION BY bucket, miner ORDER BY timestamp ASC) AS rn_first
+        FROM src";
+
+    let select = |f: &dyn Fn(&str) -> String| -> String {
+        METRIC_COLUMNS
+            .iter()
+            .map(|c| format!("{} AS {}", f(c), c))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+
+    match aggregate {
+        Aggregate::Last => format!(
+            "WITH src AS ({src}), ranked AS ({ranked})
+            SELECT name, miner, bucket AS timestamp, {columns}
+            FROM ranked WHERE rn_last = 1
+            ORDER BY bucket ASC, miner ASC",
+            columns = METRIC_COLUMNS.join(", "),
+        ),
+        Aggregate::Avg | Aggregate::Min | Aggregate::Max => {
+            let func = match aggregate {
+                Aggregate::Avg => "AVG",
+                Aggregate::Min => "MIN",
+                _ => "MAX",
+            };
+            let columns = select(&|c| {
+                if aggregate == Aggregate::Avg && INTEGER_COLUMNS.contains(&c) {
+                    format!("CAST(ROUND(AVG({})) AS BIGINT)", c)
+                } else {
+                    format!("{}({})", func, c)
+                }
+            });
+            format!(
+                "WITH src AS ({src})
+                SELECT name, miner, bucket AS timestamp, {columns}
+                FROM src GROUP BY name, miner, bucket
+                ORDER BY bucket ASC, miner ASC"
+            )
+        }
+        Aggregate::Delta => {
+            let ends = METRIC_COLUMNS
+                .iter()
+                .map(|c| {
+                    format!(
+                        "MAX(CASE WHEN rn_last = 1 THEN {c} END) AS {c}_last, \
+                        MAX(CASE WHEN rn_first = 1 THEN {c} END) AS {c}_first"
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join(", ");
+            let columns = select(&|c| {
+                format!(
+                    "{c}_last - COALESCE(LAG({c}_last) OVER (PARTITION BY miner ORDER BY bucket), {c}_first)"
+                )
+            });
+            format!(
+                "WITH src AS ({src}), ranked AS ({ranked}),
+                ends AS (SELECT name, miner, bucket, {ends} FROM ranked GROUP BY name, miner, bucket)
+                SELECT name, miner, bucket AS timestamp, {columns}
+                FROM ends
+                ORDER BY bucket ASC, miner ASC"
+            )
+        }
+    }
+}
+
+// binds name, from, to and then each miner, like `get_sql`
+pub(crate) fn aggregate_query_sql(miners: usize, bucket: Bucket, aggregate: Aggregate) -> String {
+    if miners == 0 {
+        aggregate_sql(
+            "history_total",
+            "name = ? AND timestamp > ? AND timestamp < ?",
+            bucket,
+            aggregate,
+        )
+    } else {
+        aggregate_sql(
+            "history_tiered",
+            &format!(
+                "name = ? AND timestamp > ? AND timestamp < ? AND miner IN ({})",
+                vec!["?"; miners].join(",")
+            ),
+            bucket,
+            aggregate,
+        )
+    }
+}
+
+// one point per `bucket` of <name> between time <from> and <to>, summed over
+// all miners when `miners` is empty, otherwise per listed miner
+pub async fn aggregate_db(
+    conn: SqlitePool,
+    name: String,
+    miners: Vec<String>,
+    from: i64,
+    to: i64,
+    bucket: Bucket,
+    aggregate: Aggregate,
+) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)> {
+    let sql = aggregate_query_sql(miners.len(), bucket, aggregate);
+
+    let mut query = sqlx::query_as(&sql).bind(name).bind(from).bind(to);
+    for miner in miners {
+        query = query.bind(miner);
+    }
+    let rows: Vec<HistoryRow> = query.fetch_all(&conn).await?;
+
+    let mut times = vec![];
+    let mut infos = vec![];
+    for row in rows {
+        times.push(row.timestamp);
+        infos.push(MinerInfo::from(row));
+    }
+
+    Ok((times, infos))
+}
+
+#[cfg(test)]
+async fn sample_db() -> anyhow::Result<SqlitePool> {
+    use super::db::insert_db;
+
+    let db = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?;
+    super::migrate::migrate(&db).await?;
+
+    // two miners sampled every 20s for 3 minutes, rewards grow by 1 per sample
+    for i in 0..9_i64 {
+        for miner in ["f01", "f02"] {
+            let row = HistoryRow {
+                name: "test".to_string(),
+                miner: miner.to_string(),
+                timestamp: 600 + i * 20,
+                power: i as f64,
+                rewards: i as f64,
+                blocks: i,
+                ..Default::default()
+            };
+            insert_db(db.clone(), row).await?;
+        }
+    }
+
+    Ok(db)
+}
+
+#[tokio::test]
+async fn test_aggregate_db() -> anyhow::Result<()> {
+    let db = sample_db().await?;
+    let name = "test".to_string();
+
+    let (times, infos) = aggregate_db(
+        db.clone(),
+        name.clone(),
+        vec![],
+        0,
+        10000,
+        Bucket::Minute,
+        Aggregate::Last,
+    )
+    .await?;
+    assert_eq!(times, vec![600, 660, 720]);
+    assert_eq!(infos[0].power, 4.);
+    assert_eq!(infos[2].power, 16.);
+
+    let (_, infos) = aggregate_db(
+        db.clone(),
+        name.clone(),
+        vec!["f01".to_string()],
+        0,
+        10000,
+        Bucket::Minute,
+        Aggregate::Avg,
+    )
+    .await?;
+    assert_eq!(infos.len(), 3);
+    assert_eq!(infos[1].id, "f01");
+    assert_eq!(infos[1].power, 4.);
+    assert_eq!(infos[1].blocks, 4);
+
+    let (_, infos) = aggregate_db(
+        db.clone(),
+        name.clone(),
+        vec!["f01".to_string()],
+        0,
+        10000,
+        Bucket::Minute,
+        Aggregate::Delta,
+    )
+    .await?;
+    let deltas: Vec<f64> = infos.iter().map(|i| i.rewards).collect();
+    assert_eq!(deltas, vec![2., 3., 3.]);
+
+    let (times, infos) = aggregate_db(
+        db.clone(),
+        name.clone(),
+        vec![],
+        0,
+        10000,
+        Bucket::Week,
+        Aggregate::Max,
+    )
+    .await?;
+    assert_eq!(times, vec![-3 * 86400]);
+    assert_eq!(infos[0].blocks, 16);
+
+    Ok(())
+}
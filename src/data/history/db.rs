@@ -1,119 +1,301 @@
 use std::str::FromStr;
 
-
 use lazy_static::lazy_static;
+use serde::Serialize;
 use sqlx::{
-    migrate::MigrateDatabase,
+    database::HasArguments,
+    query::Query,
     sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
-    ConnectOptions, Executor, SqlitePool, Statement,
+    ConnectOptions, Database, Encode, Executor, SqlitePool, Statement, Type,
 };
 
-use crate::data::filfox::models::MinerInfo;
+use crate::{data::filfox::models::MinerInfo, settings::settings};
+
+use super::{migrate::migrate, store::redact_url};
 
 lazy_static! {
-    pub static ref HISTORY_DB: String =
-        std::env::var("HISTORY_DB").unwrap_or_else(|_| "history.db".to_string());
+    // a sqlite file, or a postgres:// url
+    pub static ref HISTORY_DB: String = settings().history_db_url();
+    // delete, truncate, persist, memory, wal or off
+    pub static ref HISTORY_DB_JOURNAL: String =
+        std::env::var("HISTORY_DB_JOURNAL").unwrap_or_else(|_| "wal".to_string());
+    // off, normal, full or extra, normal is crash safe with wal
+    pub static ref HISTORY_DB_SYNC: String =
+        std::env::var("HISTORY_DB_SYNC").unwrap_or_else(|_| "normal".to_string());
+    // connections shared by the updaters and all handlers
+    pub static ref HISTORY_DB_POOL_SIZE: String =
+        std::env::var("HISTORY_DB_POOL_SIZE").unwrap_or_else(|_| "10".to_string());
+    // seconds a query waits for a free connection
+    pub static ref HISTORY_DB_ACQUIRE_TIMEOUT: String =
+        std::env::var("HISTORY_DB_ACQUIRE_TIMEOUT").unwrap_or_else(|_| "30".to_string());
+    // prepared statements kept per connection
+    pub static ref HISTORY_DB_STATEMENT_CACHE: String =
+        std::env::var("HISTORY_DB_STATEMENT_CACHE").unwrap_or_else(|_| "100".to_string());
+}
+
+// miner id of rows written before history was stored per miner
+pub const ALL_MINERS: &str = "all";
+
+// problems with history.db that restarting the server cannot fix
+#[derive(Debug)]
+pub enum HistoryDbError {
+    // the database was migrated by a newer build
+    UnknownSchema { found: i64, supported: i64 },
+    // an env setting has a value sqlite does not know
+    InvalidSetting { name: &'static str, value: String },
+    // the startup integrity check found damage
+    Corrupt { problems: Vec<String> },
+}
+
+impl std::fmt::Display for HistoryDbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryDbError::UnknownSchema { found, supported } => write!(
+                f,
+                "{} has schema version {} but this build only knows up to {}, refusing to start",
+                redact_url(&HISTORY_DB),
+                found,
+                supported
+            ),
+            HistoryDbError::InvalidSetting { name, value } => {
+                write!(f, "invalid value {:?} for {}", value, name)
+            }
+            HistoryDbError::Corrupt { problems } => write!(
+                f,
+                "{} failed its integrity check, restore it from a backup or move it away: {}",
+                redact_url(&HISTORY_DB),
+                problems.join("; ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HistoryDbError {}
+
+pub fn parse_setting<T: FromStr>(name: &'static str, value: &str) -> Result<T, HistoryDbError> {
+    T::from_str(value).map_err(|_| HistoryDbError::InvalidSetting {
+        name,
+        value: value.to_string(),
+    })
 }
 
+// open the pool once at startup, handlers get it through `Extension`
 pub async fn init_history_db() -> anyhow::Result<SqlitePool> {
-    let conn = if !sqlx::Sqlite::database_exists(&HISTORY_DB).await? {
-        sqlx::Sqlite::create_database(&HISTORY_DB).await?;
-        let mut options = SqliteConnectOptions::from_str(&HISTORY_DB)
-            .unwrap()
-            .journal_mode(SqliteJournalMode::Off)
-            .synchronous(SqliteSynchronous::Off);
-
-        options.log_statements(log::LevelFilter::Trace);
-        let conn = SqlitePoolOptions::new().connect_with(options).await?;
-
-        // conn.execute("PRAGMA locking_mode = EXCLUSIVE;").await?;
-        conn.execute(
-            "CREATE TABLE history (
-            id                      INTEGER PRIMARY KEY AUTOINCREMENT,
-            name                    TEXT NOT NULL,
-            timestamp               INTEGER NOT NULL,
-            pledge                  REAL NOT NULL,
-            power                   REAL NOT NULL,
-            blocks                  INTEGER NOT NULL,
-            rewards                 REAL NOT NULL
-        )",
-        )
+    let journal_mode: SqliteJournalMode = parse_setting("HISTORY_DB_JOURNAL", &HISTORY_DB_JOURNAL)?;
+    let synchronous: SqliteSynchronous = parse_setting("HISTORY_DB_SYNC", &HISTORY_DB_SYNC)?;
+    let pool_size: u32 = parse_setting("HISTORY_DB_POOL_SIZE", &HISTORY_DB_POOL_SIZE)?;
+    let acquire_timeout: u64 =
+        parse_setting("HISTORY_DB_ACQUIRE_TIMEOUT", &HISTORY_DB_ACQUIRE_TIMEOUT)?;
+    let statement_cache: usize =
+        parse_setting("HISTORY_DB_STATEMENT_CACHE", &HISTORY_DB_STATEMENT_CACHE)?;
+
+    let mut options = SqliteConnectOptions::from_str(&HISTORY_DB)
+        .unwrap()
+        .create_if_missing(true)
+        .journal_mode(journal_mode)
+        .synchronous(synchronous)
+        .statement_cache_capacity(statement_cache);
+    options.log_statements(log::LevelFilter::Trace);
+    let conn = SqlitePoolOptions::new()
+        .max_connections(pool_size.max(1))
+        .acquire_timeout(std::time::Duration::from_secs(acquire_timeout))
+        .connect_with(options)
         .await?;
 
-        conn
-    } else {
-        let mut options = SqliteConnectOptions::from_str(&HISTORY_DB)
-            .unwrap()
-            .journal_mode(SqliteJournalMode::Off)
-            .synchronous(SqliteSynchronous::Off);
-        options.log_statements(log::LevelFilter::Trace);
-        SqlitePoolOptions::new().connect_with(options).await?
-    };
+    migrate(&conn).await?;
 
     Ok(conn)
 }
 
-pub type DealDbType = (
-    String, // 0    name
-    i64,    // 1    timestamp
-    f64,    // 2    pledge
-    f64,    // 3    power
-    i64,    // 4    blocks
-    f64,    // 5    rewards
-);
-
-pub type DealDbTypeFull = (
-    i64,
-    String, // 0    name
-    i64,    // 1    timestamp
-    f64,    // 2    pledge
-    f64,    // 3    power
-    i64,    // 4    blocks
-    f64,    // 5    rewards
-);
-
-pub async fn insert_db(conn: SqlitePool, data: DealDbType) -> anyhow::Result<()> {
+// one miner, or the sum of all miners, at one sample of a history subscription
+#[derive(Debug, Clone, Default, Serialize, sqlx::FromRow)]
+pub struct HistoryRow {
+    #[sqlx(default)]
+    #[serde(skip)]
+    pub id: i64,
+    pub name: String,
+    pub miner: String,
+    pub timestamp: i64,
+    pub pledge: f64,
+    pub power: f64,
+    pub blocks: i64,
+    pub rewards: f64,
+    pub raw_power: f64,
+    pub balance: f64,
+    pub available_balance: f64,
+    pub vesting_funds: f64,
+    pub pre_commit_deposits: f64,
+    pub sectors_active: i64,
+    pub sectors_faulty: i64,
+    pub sectors_live: i64,
+    pub sectors_recovering: i64,
+    pub power_rank: i64,
+    pub raw_power_rank: i64,
+}
+
+impl HistoryRow {
+    pub fn new(name: &str, timestamp: i64, info: &MinerInfo) -> Self {
+        Self {
+            id: 0,
+            name: name.to_string(),
+            miner: info.id.clone(),
+            timestamp,
+            pledge: info.pledge,
+            power: info.power,
+            blocks: info.blocks as i64,
+            rewards: info.rewards,
+            raw_power: info.raw_power,
+            balance: info.balance,
+            available_balance: info.available_balance,
+            vesting_funds: info.vesting_funds,
+            pre_commit_deposits: info.pre_commit_deposits,
+            sectors_active: info.sectors_active,
+            sectors_faulty: info.sectors_faulty,
+            sectors_live: info.sectors_live,
+            sectors_recovering: info.sectors_recovering,
+            power_rank: info.power_rank,
+            raw_power_rank: info.raw_power_rank,
+        }
+    }
+}
+
+// value columns of `history`, in `HistoryRow` order
+pub const METRIC_COLUMNS: &[&str] = &[
+    "pledge",
+    "power",
+    "blocks",
+    "rewards",
+    "raw_power",
+    "balance",
+    "available_balance",
+    "vesting_funds",
+    "pre_commit_deposits",
+    "sectors_active",
+    "sectors_faulty",
+    "sectors_live",
+    "sectors_recovering",
+    "power_rank",
+    "raw_power_rank",
+];
+
+pub(crate) fn insert_sql() -> String {
+    format!(
+        "INSERT INTO history (name,miner,timestamp,{}) VALUES(?, ?, ?, {})
+        ON CONFLICT (name, miner, timestamp) DO NOTHING;",
+        METRIC_COLUMNS.join(","),
+        vec!["?"; METRIC_COLUMNS.len()].join(", ")
+    )
+}
+
+// bind the values of `insert_sql` for any backend
+pub(crate) fn bind_row<'q, DB>(
+    query: Query<'q, DB, <DB as HasArguments<'q>>::Arguments>,
+    data: HistoryRow,
+) -> Query<'q, DB, <DB as HasArguments<'q>>::Arguments>
+where
+    DB: Database,
+    String: Encode<'q, DB> + Type<DB>,
+    i64: Encode<'q, DB> + Type<DB>,
+    f64: Encode<'q, DB> + Type<DB>,
+{
+    query
+        .bind(data.name)
+        .bind(data.miner)
+        .bind(data.timestamp)
+        .bind(data.pledge)
+        .bind(data.power)
+        .bind(data.blocks)
+        .bind(data.rewards)
+        .bind(data.raw_power)
+        .bind(data.balance)
+        .bind(data.available_balance)
+        .bind(data.vesting_funds)
+        .bind(data.pre_commit_deposits)
+        .bind(data.sectors_active)
+        .bind(data.sectors_faulty)
+        .bind(data.sectors_live)
+        .bind(data.sectors_recovering)
+        .bind(data.power_rank)
+        .bind(data.raw_power_rank)
+}
+
+pub async fn insert_db(conn: SqlitePool, data: HistoryRow) -> anyhow::Result<()> {
+    insert_db_batch(conn, vec![data]).await?;
+    Ok(())
+}
+
+// insert all rows in a single transaction, skipping samples already stored,
+// returns the rows inserted
+pub async fn insert_db_batch(conn: SqlitePool, data: Vec<HistoryRow>) -> anyhow::Result<u64> {
+    let sql = insert_sql();
     let mut db = conn.begin().await?;
-    let stmt_with_area = conn
-        .prepare(
-            "
-            INSERT INTO history (name,timestamp,pledge,power,blocks,rewards)
-            VALUES(?, ?, ?, ?, ?, ?);",
-        )
-        .await?;
+    let stmt_with_area = (&mut *db).prepare(&sql).await?;
 
-    stmt_with_area
-        .query()
-        .bind(data.0) // name
-        .bind(data.1) // timestamp
-        .bind(data.2) // pledge
-        .bind(data.3) // power
-        .bind(data.4) // blocks
-        .bind(data.5) // rewards
-        .execute(&mut db)
-        .await?;
+    let mut inserted = 0;
+    for data in data {
+        inserted += bind_row(stmt_with_area.query(), data)
+            .execute(&mut db)
+            .await?
+            .rows_affected();
+    }
     db.commit().await?;
-    Ok(())
+    Ok(inserted)
+}
+
+// binds name, from, to and then each miner
+pub(crate) fn get_sql(miners: usize) -> String {
+    let columns = format!("id,name,miner,timestamp,{}", METRIC_COLUMNS.join(","));
+    if miners == 0 {
+        format!(
+            r#"SELECT {} from history_total
+            WHERE name=? AND timestamp > ? AND timestamp < ?
+            ORDER BY timestamp ASC"#,
+            columns
+        )
+    } else {
+        format!(
+            r#"SELECT {} from history_tiered
+            WHERE name=? AND timestamp > ? AND timestamp < ? AND miner IN ({})
+            ORDER BY timestamp ASC, miner ASC"#,
+            columns,
+            vec!["?"; miners].join(",")
+        )
+    }
+}
+
+// every row of <name> in [from, to) ordered by time, of all miners when
+// `miners` is empty, binds like `get_sql`
+pub(crate) fn export_sql(miners: usize) -> String {
+    let filter = if miners == 0 {
+        String::new()
+    } else {
+        format!(" AND miner IN ({})", vec!["?"; miners].join(","))
+    };
+    format!(
+        r#"SELECT name,miner,timestamp,{} from history_tiered
+        WHERE name=? AND timestamp >= ? AND timestamp < ?{}
+        ORDER BY timestamp ASC, miner ASC"#,
+        METRIC_COLUMNS.join(","),
+        filter
+    )
 }
 
-// get <name> between time <from> and <to>
+// get <name> between time <from> and <to>, summed over all miners when
+// `miners` is empty, otherwise one row per listed miner and sample,
+// compacted ranges come from the hourly and daily rollups
 pub async fn get_db(
     conn: SqlitePool,
     name: String,
+    miners: Vec<String>,
     from: i64,
     to: i64,
 ) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)> {
-    let sql = r#"SELECT * from history
-    WHERE name=? AND timestamp > ? AND timestamp < ?
-    ORDER BY timestamp ASC"#
-        .to_string();
-
-    let tmp: Result<Vec<DealDbTypeFull>, _> = sqlx::query_as(&sql)
-        .bind(name)
-        .bind(from)
-        .bind(to)
-        .fetch_all(&conn)
-        .await;
+    let sql = get_sql(miners.len());
+    let mut query = sqlx::query_as(&sql).bind(name).bind(from).bind(to);
+    for miner in miners {
+        query = query.bind(miner);
+    }
+    let tmp: Result<Vec<HistoryRow>, _> = query.fetch_all(&conn).await;
 
     match tmp {
         Ok(data) => {
@@ -121,7 +303,7 @@ pub async fn get_db(
             let mut infos = vec![];
 
             for t in data {
-                times.push(t.2);
+                times.push(t.timestamp);
                 let info: MinerInfo = t.into();
                 infos.push(info);
             }
@@ -132,11 +314,30 @@ pub async fn get_db(
     }
 }
 
+// a migrated in-memory database, tests leave history.db alone
+#[cfg(test)]
+async fn test_db() -> anyhow::Result<SqlitePool> {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?;
+    migrate(&pool).await?;
+
+    Ok(pool)
+}
+
 #[tokio::test]
 async fn test_get_db() -> anyhow::Result<()> {
-    let db = init_history_db().await?;
+    let db = test_db().await?;
 
-    let t = get_db(db, "test".to_string(), 1670219812 - 1, 1670219812 + 1).await?;
+    let t = get_db(
+        db,
+        "test".to_string(),
+        vec![],
+        1670219812 - 1,
+        1670219812 + 1,
+    )
+    .await?;
     dbg!(t);
 
     Ok(())
@@ -144,19 +345,72 @@ async fn test_get_db() -> anyhow::Result<()> {
 
 #[tokio::test]
 async fn test_insert_db() -> anyhow::Result<()> {
-    let db = init_history_db().await?;
+    let db = test_db().await?;
     let timestamp = chrono::Utc::now().timestamp();
 
-    let item: DealDbType = (
-        "test".to_string(),
-        timestamp + 10000,
-        825017.190309711,
-        94327.6875,
-        88404,
-        2066866.8556792436,
-    );
+    let item = HistoryRow {
+        name: "test".to_string(),
+        miner: ALL_MINERS.to_string(),
+        timestamp: timestamp + 10000,
+        pledge: 825017.190309711,
+        power: 94327.6875,
+        blocks: 88404,
+        rewards: 2066866.8556792436,
+        ..Default::default()
+    };
 
     insert_db(db, item).await?;
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_per_miner_history() -> anyhow::Result<()> {
+    let db = test_db().await?;
+    let name = "test-per-miner".to_string();
+    let timestamp = chrono::Utc::now().timestamp();
+
+    let row = |miner: &str, power: f64| HistoryRow {
+        name: name.clone(),
+        miner: miner.to_string(),
+        timestamp,
+        pledge: 1.,
+        power,
+        blocks: 1,
+        rewards: 1.,
+        sectors_faulty: 2,
+        power_rank: 10,
+        ..Default::default()
+    };
+    insert_db_batch(db.clone(), vec![row("f01", 2.), row("f02", 3.)]).await?;
+
+    let (times, infos) = get_db(
+        db.clone(),
+        name.clone(),
+        vec![],
+        timestamp - 1,
+        timestamp + 1,
+    )
+    .await?;
+    assert_eq!(times, vec![timestamp]);
+    assert_eq!(infos[0].id, ALL_MINERS);
+    assert_eq!(infos[0].power, 5.);
+    assert_eq!(infos[0].blocks, 2);
+    assert_eq!(infos[0].sectors_faulty, 4);
+    assert_eq!(infos[0].power_rank, 0);
+
+    let (_, infos) = get_db(
+        db.clone(),
+        name.clone(),
+        vec!["f02".to_string()],
+        timestamp - 1,
+        timestamp + 1,
+    )
+    .await?;
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0].id, "f02");
+    assert_eq!(infos[0].power, 3.);
+    assert_eq!(infos[0].power_rank, 10);
+
+    Ok(())
+}
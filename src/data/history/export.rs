@@ -0,0 +1,300 @@
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use super::{
+    db::{HistoryRow, METRIC_COLUMNS},
+    store::SharedStore,
+};
+
+// rows buffered between the database and the writer
+const EXPORT_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    // one json object per line
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportQuery {
+    pub name: String,
+    // all miners when empty
+    pub miners: Vec<String>,
+    pub from: i64,
+    pub to: i64,
+    pub format: ExportFormat,
+}
+
+// columns of every export, in order
+pub fn export_columns() -> Vec<&'static str> {
+    let mut columns = vec!["name", "miner", "timestamp"];
+    columns.extend_from_slice(METRIC_COLUMNS);
+    columns
+}
+
+// stream the rows of `query` into `out`, returns the number of rows written
+pub async fn export<W: Write + Send + 'static>(
+    store: SharedStore,
+    query: ExportQuery,
+    out: W,
+) -> anyhow::Result<u64> {
+    if query.format == ExportFormat::Parquet && !cfg!(feature = "parquet") {
+        anyhow::bail!("this build has no parquet support");
+    }
+
+    let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+    let format = query.format;
+    let writer = tokio::task::spawn_blocking(move || write_rows(format, rx, out));
+
+    // a failed writer drops `rx`, which ends the read early
+    let read = store
+        .stream(query.name, query.miners, query.from, query.to, tx)
+        .await;
+    let written = writer.await??;
+    read?;
+
+    Ok(written)
+}
+
+// blocking, runs until `rows` is closed
+pub fn write_rows<W: Write + Send>(
+    format: ExportFormat,
+    mut rows: mpsc::Receiver<HistoryRow>,
+    out: W,
+) -> anyhow::Result<u64> {
+    let mut written = 0;
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(out);
+            writer.write_record(export_columns())?;
+            while let Some(row) = rows.blocking_recv() {
+                writer.serialize(row)?;
+                written += 1;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Ndjson => {
+            let mut writer = std::io::BufWriter::new(out);
+            while let Some(row) = rows.blocking_recv() {
+                serde_json::to_writer(&mut writer, &row)?;
+                writer.write_all(b"\n")?;
+                written += 1;
+            }
+            writer.flush()?;
+        }
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => written = parquet::write_rows(rows, out)?,
+        #[cfg(not(feature = "parquet"))]
+        ExportFormat::Parquet => anyhow::bail!("this build has no parquet support"),
+    }
+    Ok(written)
+}
+
+#[cfg(feature = "parquet")]
+mod parquet {
+    use std::{io::Write, sync::Arc};
+
+    use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+    use arrow_schema::{DataType, Field, Schema, SchemaRef};
+    use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+    use tokio::sync::mpsc;
+
+    use super::{super::aggregate::INTEGER_COLUMNS, export_columns, HistoryRow};
+
+    // rows converted to columns at once
+    const BATCH_ROWS: usize = 8192;
+    // rows the writer keeps in memory before flushing a row group
+    const ROW_GROUP_ROWS: usize = 65536;
+
+    fn schema() -> SchemaRef {
+        let fields: Vec<Field> = export_columns()
+            .into_iter()
+            .map(|c| {
+                let data_type = match c {
+                    "name" | "miner" => DataType::Utf8,
+                    "timestamp" => DataType::Int64,
+                    c if INTEGER_COLUMNS.contains(&c) => DataType::Int64,
+                    _ => DataType::Float64,
+                };
+                Field::new(c, data_type, false)
+            })
+            .collect();
+        Arc::new(Schema::new(fields))
+    }
+
+    // columns in the order of `export_columns`
+    fn batch(schema: &SchemaRef, rows: &[HistoryRow]) -> anyhow::Result<RecordBatch> {
+        let s = |g: fn(&HistoryRow) -> &str| -> ArrayRef {
+            Arc::new(StringArray::from_iter_values(rows.iter().map(g)))
+        };
+        let i = |g: fn(&HistoryRow) -> i64| -> ArrayRef {
+            Arc::new(Int64Array::from_iter_values(rows.iter().map(g)))
+        };
+        let f = |g: fn(&HistoryRow) -> f64| -> ArrayRef {
+            Arc::new(Float64Array::from_iter_values(rows.iter().map(g)))
+        };
+
+        let columns = vec![
+            s(|r| &r.name),
+            s(|r| &r.miner),
+            i(|r| r.timestamp),
+            f(|r| r.pledge),
+            f(|r| r.power),
+            i(|r| r.blocks),
+            f(|r| r.rewards),
+            f(|r| r.raw_power),
+            f(|r| r.balance),
+            f(|r| r.available_balance),
+            f(|r| r.vesting_funds),
+            f(|r| r.pre_commit_deposits),
+            i(|r| r.sectors_active),
+            i(|r| r.sectors_faulty),
+            i(|r| r.sectors_live),
+            i(|r| r.sectors_recovering),
+            i(|r| r.power_rank),
+            i(|r| r.raw_power_rank),
+        ];
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+
+    pub fn write_rows<W: Write + Send>(
+        mut rows: mpsc::Receiver<HistoryRow>,
+        out: W,
+    ) -> anyhow::Result<u64> {
+        let schema = schema();
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_ROWS)
+            .build();
+        let mut writer = ArrowWriter::try_new(out, schema.clone(), Some(props))?;
+
+        let mut written = 0;
+        let mut pending = Vec::with_capacity(BATCH_ROWS);
+        loop {
+            let row = rows.blocking_recv();
+            let done = row.is_none();
+            pending.extend(row);
+            if pending.len() == BATCH_ROWS || (done && !pending.is_empty()) {
+                writer.write(&batch(&schema, &pending)?)?;
+                written += pending.len() as u64;
+                pending.clear();
+            }
+            if done {
+                break;
+            }
+        }
+        writer.close()?;
+
+        Ok(written)
+    }
+}
+
+#[tokio::test]
+async fn test_export() -> anyhow::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    use super::{db::insert_db, store::SqliteStore};
+
+    // a `Write` the test can read back after the export moved it away
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?;
+    super::migrate::migrate(&pool).await?;
+    for timestamp in 0..5 {
+        for miner in ["f01", "f02"] {
+            let row = HistoryRow {
+                name: "test".to_string(),
+                miner: miner.to_string(),
+                timestamp,
+                power: 1.5,
+                blocks: timestamp,
+                ..Default::default()
+            };
+            insert_db(pool.clone(), row).await?;
+        }
+    }
+    let store: SharedStore = Arc::new(SqliteStore { pool });
+
+    let query = |format| ExportQuery {
+        name: "test".to_string(),
+        miners: vec!["f02".to_string()],
+        from: 1,
+        to: 4,
+        format,
+    };
+
+    let out = Shared::default();
+    assert_eq!(
+        export(store.clone(), query(ExportFormat::Csv), out.clone()).await?,
+        3
+    );
+    let csv = String::from_utf8(out.0.lock().unwrap().clone())?;
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], export_columns().join(","));
+    assert!(lines[1].starts_with("test,f02,1,0.0,1.5,1,"));
+
+    let out = Shared::default();
+    assert_eq!(
+        export(store.clone(), query(ExportFormat::Ndjson), out.clone()).await?,
+        3
+    );
+    let ndjson = String::from_utf8(out.0.lock().unwrap().clone())?;
+    let first: serde_json::Value = serde_json::from_str(ndjson.lines().next().unwrap())?;
+    assert_eq!(first["miner"], "f02");
+    assert_eq!(first["blocks"], 1);
+    assert!(first.get("id").is_none());
+
+    #[cfg(feature = "parquet")]
+    {
+        use ::parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let out = Shared::default();
+        assert_eq!(
+            export(store.clone(), query(ExportFormat::Parquet), out.clone()).await?,
+            3
+        );
+        let bytes = out.0.lock().unwrap().clone();
+        let reader = SerializedFileReader::new(axum::body::Bytes::from(bytes))?;
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    aggregate::INTEGER_COLUMNS,
+    db::{HistoryRow, METRIC_COLUMNS},
+    store::SharedStore,
+    subscribe::GlobalHistory,
+    *,
+};
+
+// rows inserted per transaction
+const IMPORT_BATCH: usize = 1000;
+// invalid rows listed in a report, the rest are only counted
+const MAX_ERRORS: usize = 100;
+// samples further in the future than this are rejected, in seconds
+const MAX_CLOCK_SKEW: i64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    // with a header row, columns as in the export
+    Csv,
+    // one json object per line
+    Ndjson,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportError {
+    pub line: u64,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub name: String,
+    // rows read from the input
+    pub rows: u64,
+    pub inserted: u64,
+    // rows with a sample already stored for the same miner and time
+    pub duplicates: u64,
+    pub invalid: u64,
+    pub errors: Vec<ImportError>,
+    // validated only, nothing written
+    pub dry_run: bool,
+}
+
+impl ImportReport {
+    fn invalid(&mut self, line: u64, error: String) {
+        self.invalid += 1;
+        if self.errors.len() < MAX_ERRORS {
+            self.errors.push(ImportError { line, error });
+        }
+    }
+}
+
+// unix seconds, rfc3339, "YYYY-MM-DD HH:MM:SS" or "YYYY-MM-DD" in utc
+fn parse_timestamp(value: &str) -> anyhow::Result<i64> {
+    let value = value.trim();
+    if let Ok(t) = value.parse::<i64>() {
+        return Ok(t);
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return Ok(t.timestamp());
+    }
+    if let Ok(t) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return Ok(t.and_utc().timestamp());
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(d.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp());
+    }
+    anyhow::bail!("invalid timestamp {:?}", value)
+}
+
+fn set_metric(row: &mut HistoryRow, column: &str, value: &str) -> anyhow::Result<()> {
+    let value = value.trim();
+    // missing metrics are stored as 0
+    if value.is_empty() {
+        return Ok(());
+    }
+
+    if INTEGER_COLUMNS.contains(&column) {
+        let v: i64 = value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{} must be an integer, got {:?}", column, value))?;
+        if v < 0 {
+            anyhow::bail!("{} must not be negative", column);
+        }
+        match column {
+            "blocks" => row.blocks = v,
+            "sectors_active" => row.sectors_active = v,
+            "sectors_faulty" => row.sectors_faulty = v,
+            "sectors_live" => row.sectors_live = v,
+            "sectors_recovering" => row.sectors_recovering = v,
+            "power_rank" => row.power_rank = v,
+            "raw_power_rank" => row.raw_power_rank = v,
+            _ => unreachable!("unknown integer column {}", column),
+        }
+    } else {
+        let v: f64 = value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{} must be a number, got {:?}", column, value))?;
+        if !v.is_finite() {
+            anyhow::bail!("{} must be finite", column);
+        }
+        match column {
+            "pledge" => row.pledge = v,
+            "power" => row.power = v,
+            "rewards" => row.rewards = v,
+            "raw_power" => row.raw_power = v,
+            "balance" => row.balance = v,
+            "available_balance" => row.available_balance = v,
+            "vesting_funds" => row.vesting_funds = v,
+            "pre_commit_deposits" => row.pre_commit_deposits = v,
+            _ => unreachable!("unknown float column {}", column),
+        }
+    }
+    Ok(())
+}
+
+// a column this import reads, anything else is likely a typo that would
+// otherwise store zeros
+fn check_column(column: &str) -> anyhow::Result<()> {
+    if ["name", "miner", "timestamp"].contains(&column) || METRIC_COLUMNS.contains(&column) {
+        return Ok(());
+    }
+    anyhow::bail!("unknown column {:?}", column)
+}
+
+// one input row as column -> text, checked into a row of subscription <name>
+fn parse_row(name: &str, fields: &HashMap<String, String>, now: i64) -> anyhow::Result<HistoryRow> {
+    let mut columns: Vec<&String> = fields.keys().collect();
+    columns.sort();
+    for column in columns {
+        check_column(column)?;
+    }
+    let field = |c: &str| fields.get(c).map(|v| v.trim()).unwrap_or_default();
+
+    // the name column is optional, but must not point at another subscription
+    let row_name = field("name");
+    if !row_name.is_empty() && row_name != name {
+        anyhow::bail!(
+            "row is for history {:?}, importing into {:?}",
+            row_name,
+            name
+        );
+    }
+    let miner = field("miner");
+    if miner.is_empty() {
+        anyhow::bail!("miner is missing");
+    }
+    let timestamp = parse_timestamp(field("timestamp"))?;
+    if timestamp <= 0 || timestamp > now + MAX_CLOCK_SKEW {
+        anyhow::bail!("timestamp {} is out of range", timestamp);
+    }
+
+    let mut row = HistoryRow {
+        name: name.to_string(),
+        miner: miner.to_string(),
+        timestamp,
+        ..Default::default()
+    };
+    for column in METRIC_COLUMNS {
+        set_metric(&mut row, column, field(column))?;
+    }
+    Ok(row)
+}
+
+// the fields of one input line, or why it cannot be read
+type Record = Result<HashMap<String, String>, String>;
+// (errors of the csv header as (line, error), (line, record) of each row)
+type Records = (Vec<(u64, String)>, Vec<(u64, Record)>);
+
+// split `input` into (line, record), unknown csv columns are reported once
+// against the header and left out of the rows
+fn read_records(format: ImportFormat, input: &[u8]) -> anyhow::Result<Records> {
+    let mut header_errors = vec![];
+    let mut records = vec![];
+    match format {
+        ImportFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(input);
+            let headers = reader.headers()?.clone();
+            for header in headers.iter() {
+                if let Err(e) = check_column(header) {
+                    header_errors.push((1, e.to_string()));
+                }
+            }
+            for record in reader.records() {
+                match record {
+                    Ok(record) => {
+                        let line = record.position().map(|p| p.line()).unwrap_or_default();
+                        let fields = headers
+                            .iter()
+                            .zip(record.iter())
+                            .filter(|(h, _)| check_column(h).is_ok())
+                            .map(|(h, v)| (h.to_string(), v.to_string()))
+                            .collect();
+                        records.push((line, Ok(fields)));
+                    }
+                    Err(e) => {
+                        let line = e.position().map(|p| p.line()).unwrap_or_default();
+                        records.push((line, Err(e.to_string())));
+                    }
+                }
+            }
+        }
+        ImportFormat::Ndjson => {
+            for (idx, line) in String::from_utf8_lossy(input).lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let fields =
+                    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(line)
+                        .map(|object| {
+                            object
+                                .into_iter()
+                                .map(|(k, v)| {
+                                    let v = match v {
+                                        serde_json::Value::String(s) => s,
+                                        serde_json::Value::Null => String::new(),
+                                        v => v.to_string(),
+                                    };
+                                    (k, v)
+                                })
+                                .collect()
+                        })
+                        .map_err(|e| e.to_string());
+                records.push((idx as u64 + 1, fields));
+            }
+        }
+    }
+    Ok((header_errors, records))
+}
+
+// validate every row of `input` and, when all are valid and it is not a dry
+// run, insert them into history <name> in batches, skipping stored samples
+pub async fn import(
+    store: &SharedStore,
+    name: &str,
+    format: ImportFormat,
+    input: &[u8],
+    dry_run: bool,
+) -> anyhow::Result<ImportReport> {
+    import_into(&GLOBAL_HISTORY, store, name, format, input, dry_run).await
+}
+
+// `import` checking `name` against the given subscriptions
+async fn import_into(
+    history: &GlobalHistory,
+    store: &SharedStore,
+    name: &str,
+    format: ImportFormat,
+    input: &[u8],
+    dry_run: bool,
+) -> anyhow::Result<ImportReport> {
+    // backfill only subscriptions that exist
+    history.get_history(name.to_string()).await?;
+
+    let now = Utc::now().timestamp();
+    let mut report = ImportReport {
+        name: name.to_string(),
+        dry_run,
+        ..Default::default()
+    };
+
+    let (header_errors, records) = read_records(format, input)?;
+    for (line, e) in header_errors {
+        report.invalid(line, e);
+    }
+    let mut rows = vec![];
+    for (line, record) in records {
+        report.rows += 1;
+        match record.and_then(|fields| parse_row(name, &fields, now).map_err(|e| e.to_string())) {
+            Ok(row) => rows.push(row),
+            Err(e) => report.invalid(line, e),
+        }
+    }
+
+    // all or nothing, a half imported file is hard to fix up
+    if report.invalid > 0 || dry_run {
+        return Ok(report);
+    }
+
+    for batch in rows.chunks(IMPORT_BATCH) {
+        report.inserted += store.insert(batch.to_vec()).await?;
+    }
+    report.duplicates = rows.len() as u64 - report.inserted;
+
+    Ok(report)
+}
+
+#[tokio::test]
+async fn test_import() -> anyhow::Result<()> {
+    use std::sync::Arc;
+
+    use super::{store::SqliteStore, subscribe::HistoryItem};
+
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?;
+    super::migrate::migrate(&pool).await?;
+    let store: SharedStore = Arc::new(SqliteStore { pool });
+
+    // subscriptions of its own, the state file is left alone
+    let name = "test_import".to_string();
+    let history = GlobalHistory {
+        history: vec![HistoryItem {
+            name: name.clone(),
+            interval: 60,
+            add_time: 0,
+            retention: Default::default(),
+            owner: None,
+        }]
+        .into(),
+        last_update: vec![0].into(),
+    };
+
+    let csv = "miner,timestamp,power,blocks\n\
+        f01,2023-01-01,1.5,3\n\
+        f01,2023-01-02 00:00:00,2.5,\n\
+        f02,1672531200,1,1\n";
+    let report = import_into(
+        &history,
+        &store,
+        &name,
+        ImportFormat::Csv,
+        csv.as_bytes(),
+        false,
+    )
+    .await?;
+    assert_eq!((report.rows, report.inserted, report.invalid), (3, 3, 0));
+
+    // the same samples again are skipped
+    let ndjson = r#"{"name":"test_import","miner":"f01","timestamp":1672531200,"power":9}
+
+{"miner":"f03","timestamp":"2023-01-03T00:00:00Z","blocks":2}"#;
+    let report = import_into(
+        &history,
+        &store,
+        &name,
+        ImportFormat::Ndjson,
+        ndjson.as_bytes(),
+        false,
+    )
+    .await?;
+    assert_eq!((report.rows, report.inserted, report.duplicates), (2, 1, 1));
+
+    let (times, infos) = store.get(name.clone(), vec![], 0, i64::MAX).await?;
+    assert_eq!(times, vec![1672531200, 1672617600, 1672704000]);
+    assert_eq!(infos[0].power, 2.5);
+    assert_eq!(infos[0].blocks, 4);
+
+    // one bad row rejects the whole file
+    let bad = "miner,timestamp,power,blocks\n\
+        f04,2023-01-04,1,1\n\
+        ,2023-01-04,1,1\n\
+        f04,yesterday,1,1\n\
+        f04,2023-01-05,lots,1.5\n";
+    let report = import_into(
+        &history,
+        &store,
+        &name,
+        ImportFormat::Csv,
+        bad.as_bytes(),
+        false,
+    )
+    .await?;
+    assert_eq!((report.rows, report.inserted, report.invalid), (4, 0, 3));
+    assert_eq!(report.errors[0].line, 3);
+    assert!(report.errors[1].error.contains("timestamp"));
+
+    // unknown columns are refused, once for a csv header, per line for ndjson
+    let typo = "miner,timestamp,powr\n\
+        f05,2023-01-05,1\n";
+    let report = import_into(
+        &history,
+        &store,
+        &name,
+        ImportFormat::Csv,
+        typo.as_bytes(),
+        false,
+    )
+    .await?;
+    assert_eq!((report.rows, report.inserted, report.invalid), (1, 0, 1));
+    assert_eq!(report.errors[0].line, 1);
+    assert!(report.errors[0].error.contains("powr"));
+    let typo = r#"{"miner":"f05","timestamp":1672531200,"powr":1}"#;
+    let report = import_into(
+        &history,
+        &store,
+        &name,
+        ImportFormat::Ndjson,
+        typo.as_bytes(),
+        true,
+    )
+    .await?;
+    assert_eq!(report.invalid, 1);
+    assert!(report.errors[0].error.contains("powr"));
+
+    // another subscription's rows are refused
+    let other = r#"{"name":"other","miner":"f01","timestamp":1672531200}"#;
+    let report = import_into(
+        &history,
+        &store,
+        &name,
+        ImportFormat::Ndjson,
+        other.as_bytes(),
+        true,
+    )
+    .await?;
+    assert_eq!(report.invalid, 1);
+
+    assert!(import_into(
+        &history,
+        &store,
+        "other",
+        ImportFormat::Csv,
+        csv.as_bytes(),
+        true
+    )
+    .await
+    .is_err());
+
+    Ok(())
+}
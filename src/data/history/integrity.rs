@@ -0,0 +1,194 @@
+use std::str::FromStr;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use tokio::sync::RwLock;
+
+use super::{
+    db::{parse_setting, HistoryDbError, HISTORY_DB},
+    migrate::{latest_version, schema_version},
+};
+
+// problems reported by one check, the rest is dropped
+const MAX_PROBLEMS: i64 = 100;
+
+lazy_static! {
+    // off, quick or full, run once at startup
+    pub static ref HISTORY_DB_CHECK: String =
+        std::env::var("HISTORY_DB_CHECK").unwrap_or_else(|_| "quick".to_string());
+    // result of the startup check
+    pub static ref LAST_INTEGRITY: RwLock<Option<IntegrityReport>> = RwLock::new(None);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IntegrityCheck {
+    Off,
+    // `PRAGMA quick_check`, skips index consistency, fast on large files
+    Quick,
+    // `PRAGMA integrity_check`
+    Full,
+}
+
+impl FromStr for IntegrityCheck {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_ascii_lowercase() {
+            "off" => Ok(IntegrityCheck::Off),
+            "quick" => Ok(IntegrityCheck::Quick),
+            "full" => Ok(IntegrityCheck::Full),
+            _ => Err(anyhow::anyhow!("unknown integrity check {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub check: IntegrityCheck,
+    pub ok: bool,
+    pub problems: Vec<String>,
+    pub checked_at: i64,
+    pub took_ms: u64,
+}
+
+pub async fn check_integrity(
+    conn: &SqlitePool,
+    check: IntegrityCheck,
+) -> anyhow::Result<IntegrityReport> {
+    let start = std::time::Instant::now();
+    let pragma = match check {
+        IntegrityCheck::Off => None,
+        IntegrityCheck::Quick => Some("quick_check"),
+        IntegrityCheck::Full => Some("integrity_check"),
+    };
+
+    let mut problems = vec![];
+    if let Some(pragma) = pragma {
+        let sql = format!("PRAGMA {}({})", pragma, MAX_PROBLEMS);
+        for row in sqlx::query(&sql).fetch_all(conn).await? {
+            let line: String = row.try_get(0)?;
+            // a healthy database reports a single "ok"
+            if line != "ok" {
+                problems.push(line);
+            }
+        }
+    }
+
+    Ok(IntegrityReport {
+        check,
+        ok: problems.is_empty(),
+        problems,
+        checked_at: Utc::now().timestamp(),
+        took_ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+// check history.db as configured by `HISTORY_DB_CHECK`, a damaged database
+// fails with `HistoryDbError::Corrupt` instead of serving bad history
+pub async fn startup_check(conn: &SqlitePool) -> anyhow::Result<()> {
+    let check: IntegrityCheck = parse_setting("HISTORY_DB_CHECK", &HISTORY_DB_CHECK)?;
+    let report = check_integrity(conn, check).await?;
+    tracing::info!(
+        "{} integrity check {:?}: ok={} in {}ms",
+        *HISTORY_DB,
+        check,
+        report.ok,
+        report.took_ms
+    );
+
+    let problems = report.problems.clone();
+    *LAST_INTEGRITY.write().await = Some(report);
+
+    if !problems.is_empty() {
+        return Err(HistoryDbError::Corrupt { problems }.into());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DbStatus {
+    // "sqlite" or "postgres"
+    pub backend: String,
+    // file or url without password
+    pub path: String,
+    pub schema_version: i64,
+    pub latest_version: i64,
+    pub size_bytes: i64,
+    // sqlite only
+    pub journal_mode: Option<String>,
+    pub synchronous: Option<String>,
+    pub page_size: Option<i64>,
+    pub page_count: Option<i64>,
+    // pages freed by deletes, reused before the file grows
+    pub freelist_count: Option<i64>,
+    pub integrity: Option<IntegrityReport>,
+    // postgres only
+    pub timescale: Option<bool>,
+}
+
+async fn pragma_i64(conn: &SqlitePool, pragma: &str) -> anyhow::Result<i64> {
+    let row = sqlx::query(&format!("PRAGMA {}", pragma))
+        .fetch_one(conn)
+        .await?;
+    Ok(row.try_get(0)?)
+}
+
+pub async fn db_status(conn: &SqlitePool) -> anyhow::Result<DbStatus> {
+    let journal_mode: String = sqlx::query("PRAGMA journal_mode")
+        .fetch_one(conn)
+        .await?
+        .try_get(0)?;
+    let synchronous = match pragma_i64(conn, "synchronous").await? {
+        0 => "off",
+        1 => "normal",
+        2 => "full",
+        3 => "extra",
+        _ => "unknown",
+    };
+    let page_size = pragma_i64(conn, "page_size").await?;
+    let page_count = pragma_i64(conn, "page_count").await?;
+
+    Ok(DbStatus {
+        backend: "sqlite".to_string(),
+        path: HISTORY_DB.to_string(),
+        schema_version: schema_version(conn).await?,
+        latest_version: latest_version(),
+        size_bytes: page_size * page_count,
+        journal_mode: Some(journal_mode),
+        synchronous: Some(synchronous.to_string()),
+        page_size: Some(page_size),
+        page_count: Some(page_count),
+        freelist_count: Some(pragma_i64(conn, "freelist_count").await?),
+        integrity: LAST_INTEGRITY.read().await.clone(),
+        timescale: None,
+    })
+}
+
+#[tokio::test]
+async fn test_check_integrity() -> anyhow::Result<()> {
+    let db = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?;
+    super::migrate::migrate(&db).await?;
+
+    for check in [IntegrityCheck::Quick, IntegrityCheck::Full] {
+        let report = check_integrity(&db, check).await?;
+        assert!(report.ok, "{:?}", report.problems);
+    }
+
+    let status = db_status(&db).await?;
+    assert_eq!(status.schema_version, latest_version());
+    assert_eq!(status.journal_mode.as_deref(), Some("memory"));
+
+    assert!(parse_setting::<IntegrityCheck>("HISTORY_DB_CHECK", "FULL").is_ok());
+    assert!(matches!(
+        parse_setting::<IntegrityCheck>("HISTORY_DB_CHECK", "sometimes"),
+        Err(HistoryDbError::InvalidSetting { .. })
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use chrono::Utc;
+use lazy_static::lazy_static;
+use sqlx::{Executor, Row, SqlitePool};
+use tokio::sync::Mutex;
+
+use super::db::HistoryDbError;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+// every schema change of history.db, in order, never edit a released one
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "history",
+        sql: include_str!("../../../migrations/0001_history.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "per_miner",
+        sql: include_str!("../../../migrations/0002_per_miner.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "metrics",
+        sql: include_str!("../../../migrations/0003_metrics.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "retention",
+        sql: include_str!("../../../migrations/0004_retention.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "unique_samples",
+        sql: include_str!("../../../migrations/0005_unique_samples.sql"),
+    },
+];
+
+lazy_static! {
+    // pools opened at the same time in one process must not migrate concurrently
+    static ref MIGRATE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+// highest migration applied to `conn`, 0 for an empty database
+pub async fn schema_version(conn: &SqlitePool) -> anyhow::Result<i64> {
+    let row = sqlx::query("SELECT COALESCE(MAX(version), 0) AS version FROM schema_migrations")
+        .fetch_one(conn)
+        .await?;
+    Ok(row.get("version"))
+}
+
+async fn table_exists(conn: &SqlitePool, name: &str) -> anyhow::Result<bool> {
+    let row = sqlx::query("SELECT COUNT(*) AS n FROM sqlite_master WHERE type='table' AND name=?")
+        .bind(name)
+        .fetch_one(conn)
+        .await?;
+    Ok(row.get::<i64, _>("n") > 0)
+}
+
+async fn column_exists(conn: &SqlitePool, table: &str, column: &str) -> anyhow::Result<bool> {
+    let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+        .fetch_all(conn)
+        .await?;
+    Ok(columns.iter().any(|c| c.get::<String, _>("name") == column))
+}
+
+// version of a database created before migrations were recorded
+async fn legacy_version(conn: &SqlitePool) -> anyhow::Result<i64> {
+    if !table_exists(conn, "history").await? {
+        return Ok(0);
+    }
+    if column_exists(conn, "history", "miner").await? {
+        return Ok(2);
+    }
+    Ok(1)
+}
+
+// bring `conn` up to the latest schema, refusing schemas newer than this build
+pub async fn migrate(conn: &SqlitePool) -> anyhow::Result<()> {
+    let _lock = MIGRATE_LOCK.lock().await;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version                 INTEGER PRIMARY KEY,
+            name                    TEXT NOT NULL,
+            applied_at              INTEGER NOT NULL
+        )",
+    )
+    .await?;
+
+    let mut current = schema_version(conn).await?;
+    if current == 0 {
+        let legacy = legacy_version(conn).await?;
+        for m in MIGRATIONS.iter().filter(|m| m.version <= legacy) {
+            tracing::info!(
+                "history db: recording existing schema {} {}",
+                m.version,
+                m.name
+            );
+            record(conn, m).await?;
+        }
+        current = legacy;
+    }
+
+    let latest = latest_version();
+    if current > latest {
+        return Err(HistoryDbError::UnknownSchema {
+            found: current,
+            supported: latest,
+        }
+        .into());
+    }
+
+    for m in MIGRATIONS.iter().filter(|m| m.version > current) {
+        tracing::info!("history db: applying migration {} {}", m.version, m.name);
+        let mut tx = conn.begin().await?;
+        tx.execute(m.sql).await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)")
+            .bind(m.version)
+            .bind(m.name)
+            .bind(Utc::now().timestamp())
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+async fn record(conn: &SqlitePool, m: &Migration) -> anyhow::Result<()> {
+    sqlx::query("INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)")
+        .bind(m.version)
+        .bind(m.name)
+        .bind(Utc::now().timestamp())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+async fn memory_db() -> anyhow::Result<SqlitePool> {
+    Ok(sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?)
+}
+
+#[tokio::test]
+async fn test_migrate_fresh_db() -> anyhow::Result<()> {
+    let db = memory_db().await?;
+
+    migrate(&db).await?;
+    assert_eq!(schema_version(&db).await?, latest_version());
+    assert!(column_exists(&db, "history", "miner").await?);
+
+    // running again is a no-op
+    migrate(&db).await?;
+    assert_eq!(schema_version(&db).await?, latest_version());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_db() -> anyhow::Result<()> {
+    let db = memory_db().await?;
+    db.execute(MIGRATIONS[0].sql).await?;
+    db.execute("INSERT INTO history (name,timestamp,pledge,power,blocks,rewards) VALUES ('old', 1, 1, 2, 3, 4)")
+        .await?;
+
+    migrate(&db).await?;
+    assert_eq!(schema_version(&db).await?, latest_version());
+
+    let row = sqlx::query("SELECT miner FROM history WHERE name = 'old'")
+        .fetch_one(&db)
+        .await?;
+    assert_eq!(row.get::<String, _>("miner"), "all");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_refuses_newer_schema() -> anyhow::Result<()> {
+    let db = memory_db().await?;
+    migrate(&db).await?;
+    sqlx::query(
+        "INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, 'future', 0)",
+    )
+    .bind(latest_version() + 1)
+    .execute(&db)
+    .await?;
+
+    let err = migrate(&db).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<HistoryDbError>(),
+        Some(HistoryDbError::UnknownSchema { .. })
+    ));
+
+    Ok(())
+}
@@ -1,4 +1,12 @@
+pub mod aggregate;
 pub mod db;
+pub mod export;
+pub mod import;
+pub mod integrity;
+pub mod migrate;
+pub mod postgres;
+pub mod retention;
+pub mod store;
 pub mod subscribe;
 pub mod update;
 
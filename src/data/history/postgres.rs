@@ -0,0 +1,416 @@
+use std::{path::Path, str::FromStr};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::TryStreamExt;
+use lazy_static::lazy_static;
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    ConnectOptions, Executor, PgPool, Row,
+};
+use tokio::sync::mpsc;
+
+use crate::data::filfox::models::MinerInfo;
+
+use super::{
+    aggregate::{aggregate_query_sql, Aggregate, Bucket},
+    db::{
+        bind_row, export_sql, get_sql, insert_sql, parse_setting, HistoryDbError, HistoryRow,
+        HISTORY_DB_ACQUIRE_TIMEOUT, HISTORY_DB_POOL_SIZE, HISTORY_DB_STATEMENT_CACHE,
+    },
+    integrity::DbStatus,
+    migrate::Migration,
+    retention::{cutoffs, rollup_sql, CompactStats, Retention, DAY, HOUR},
+    store::{redact_url, HistoryStore},
+};
+
+// every schema change of the postgres backend, versioned separately from sqlite
+pub const PG_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "history",
+        sql: include_str!("../../../migrations/postgres/0001_history.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "unique_samples",
+        sql: include_str!("../../../migrations/postgres/0002_unique_samples.sql"),
+    },
+];
+
+// key of the advisory lock held while migrating, shared by all processes
+const MIGRATE_LOCK_ID: i64 = 0x0068_6973_746f_7279;
+// span of one timescale chunk, in seconds
+const CHUNK_INTERVAL: i64 = 7 * 86400;
+
+lazy_static! {
+    // turn `history` into a timescale hypertable, needs the extension installed
+    pub static ref HISTORY_DB_TIMESCALE: String =
+        std::env::var("HISTORY_DB_TIMESCALE").unwrap_or_else(|_| "false".to_string());
+}
+
+pub fn pg_latest_version() -> i64 {
+    PG_MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+// the shared sql uses sqlite's `?`, postgres numbers its placeholders
+pub(crate) fn pg_sql(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut n = 0;
+    for c in sql.chars() {
+        if c == '?' {
+            n += 1;
+            out.push_str(&format!("${}", n));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+pub struct PgStore {
+    pub pool: PgPool,
+    pub url: String,
+    pub timescale: bool,
+}
+
+impl PgStore {
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let pool_size: u32 = parse_setting("HISTORY_DB_POOL_SIZE", &HISTORY_DB_POOL_SIZE)?;
+        let acquire_timeout: u64 =
+            parse_setting("HISTORY_DB_ACQUIRE_TIMEOUT", &HISTORY_DB_ACQUIRE_TIMEOUT)?;
+        let statement_cache: usize =
+            parse_setting("HISTORY_DB_STATEMENT_CACHE", &HISTORY_DB_STATEMENT_CACHE)?;
+        let timescale: bool = parse_setting("HISTORY_DB_TIMESCALE", &HISTORY_DB_TIMESCALE)?;
+
+        let mut options =
+            PgConnectOptions::from_str(url)?.statement_cache_capacity(statement_cache);
+        options.log_statements(log::LevelFilter::Trace);
+        let pool = PgPoolOptions::new()
+            .max_connections(pool_size.max(1))
+            .acquire_timeout(std::time::Duration::from_secs(acquire_timeout))
+            .connect_with(options)
+            .await?;
+
+        let store = Self {
+            pool,
+            url: url.to_string(),
+            timescale,
+        };
+        store.migrate().await?;
+        if timescale {
+            store.enable_timescale().await?;
+        }
+
+        Ok(store)
+    }
+
+    pub async fn schema_version(&self) -> anyhow::Result<i64> {
+        let row = sqlx::query("SELECT COALESCE(MAX(version), 0)::BIGINT FROM schema_migrations")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(row.try_get(0)?)
+    }
+
+    // bring the database up to the latest schema, like `migrate::migrate`
+    pub async fn migrate(&self) -> anyhow::Result<()> {
+        self.pool
+            .execute(
+                "CREATE TABLE IF NOT EXISTS schema_migrations (
+                    version                 BIGINT PRIMARY KEY,
+                    name                    TEXT NOT NULL,
+                    applied_at              BIGINT NOT NULL
+                )",
+            )
+            .await?;
+
+        let mut tx = self.pool.begin().await?;
+        // other processes sharing the database wait until this one is done
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(MIGRATE_LOCK_ID)
+            .execute(&mut tx)
+            .await?;
+        let current: i64 =
+            sqlx::query("SELECT COALESCE(MAX(version), 0)::BIGINT FROM schema_migrations")
+                .fetch_one(&mut tx)
+                .await?
+                .try_get(0)?;
+
+        let latest = pg_latest_version();
+        if current > latest {
+            return Err(HistoryDbError::UnknownSchema {
+                found: current,
+                supported: latest,
+            }
+            .into());
+        }
+
+        for m in PG_MIGRATIONS.iter().filter(|m| m.version > current) {
+            tracing::info!("history db: applying migration {} {}", m.version, m.name);
+            tx.execute(m.sql).await?;
+            sqlx::query(
+                "INSERT INTO schema_migrations (version, name, applied_at) VALUES ($1, $2, $3)",
+            )
+            .bind(m.version)
+            .bind(m.name)
+            .bind(Utc::now().timestamp())
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn enable_timescale(&self) -> anyhow::Result<()> {
+        self.pool
+            .execute("CREATE EXTENSION IF NOT EXISTS timescaledb")
+            .await?;
+        sqlx::query(
+            "SELECT create_hypertable('history', 'timestamp',
+                chunk_time_interval => $1::BIGINT, if_not_exists => TRUE, migrate_data => TRUE)",
+        )
+        .bind(CHUNK_INTERVAL)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn fetch_series(
+        &self,
+        sql: &str,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+    ) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)> {
+        let sql = pg_sql(sql);
+        let mut query = sqlx::query_as(&sql).bind(name).bind(from).bind(to);
+        for miner in miners {
+            query = query.bind(miner);
+        }
+        let rows: Vec<HistoryRow> = query.fetch_all(&self.pool).await?;
+
+        let times = rows.iter().map(|r| r.timestamp).collect();
+        let infos = rows.into_iter().map(MinerInfo::from).collect();
+        Ok((times, infos))
+    }
+}
+
+#[async_trait]
+impl HistoryStore for PgStore {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn insert(&self, rows: Vec<HistoryRow>) -> anyhow::Result<u64> {
+        let sql = pg_sql(&insert_sql());
+        let mut tx = self.pool.begin().await?;
+        let mut inserted = 0;
+        for row in rows {
+            inserted += bind_row(sqlx::query(&sql), row)
+                .execute(&mut tx)
+                .await?
+                .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    async fn get(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+    ) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)> {
+        let sql = get_sql(miners.len());
+        self.fetch_series(&sql, name, miners, from, to).await
+    }
+
+    async fn aggregate(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+        bucket: Bucket,
+        aggregate: Aggregate,
+    ) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)> {
+        let sql = aggregate_query_sql(miners.len(), bucket, aggregate);
+        self.fetch_series(&sql, name, miners, from, to).await
+    }
+
+    async fn stream(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+        rows: mpsc::Sender<HistoryRow>,
+    ) -> anyhow::Result<()> {
+        let sql = pg_sql(&export_sql(miners.len()));
+        let mut query = sqlx::query_as(&sql).bind(name).bind(from).bind(to);
+        for miner in miners {
+            query = query.bind(miner);
+        }
+
+        let mut stream = query.fetch(&self.pool);
+        while let Some(row) = stream.try_next().await? {
+            if rows.send(row).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn compact(
+        &self,
+        name: &str,
+        retention: &Retention,
+        now: i64,
+    ) -> anyhow::Result<CompactStats> {
+        let mut stats = CompactStats::default();
+        let mut tx = self.pool.begin().await?;
+        let (raw_before, hourly_before) = cutoffs(retention, now);
+
+        if retention.raw > 0 {
+            let sql = pg_sql(&rollup_sql("history", "history_hourly", HOUR));
+            tx.execute(sqlx::query(&sql).bind(name).bind(raw_before))
+                .await?;
+            stats.raw = tx
+                .execute(
+                    sqlx::query("DELETE FROM history WHERE name = $1 AND timestamp < $2")
+                        .bind(name)
+                        .bind(raw_before),
+                )
+                .await?
+                .rows_affected();
+        }
+        if retention.hourly > 0 {
+            let sql = pg_sql(&rollup_sql("history_hourly", "history_daily", DAY));
+            tx.execute(sqlx::query(&sql).bind(name).bind(hourly_before))
+                .await?;
+            stats.hourly = tx
+                .execute(
+                    sqlx::query("DELETE FROM history_hourly WHERE name = $1 AND timestamp < $2")
+                        .bind(name)
+                        .bind(hourly_before),
+                )
+                .await?
+                .rows_affected();
+        }
+        if retention.daily > 0 {
+            stats.daily = tx
+                .execute(
+                    sqlx::query("DELETE FROM history_daily WHERE name = $1 AND timestamp < $2")
+                        .bind(name)
+                        .bind(now - retention.daily),
+                )
+                .await?
+                .rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(stats)
+    }
+
+    async fn status(&self) -> anyhow::Result<DbStatus> {
+        let size_bytes: i64 = sqlx::query(
+            "SELECT (pg_total_relation_size('history')
+                + pg_total_relation_size('history_hourly')
+                + pg_total_relation_size('history_daily'))::BIGINT",
+        )
+        .fetch_one(&self.pool)
+        .await?
+        .try_get(0)?;
+
+        Ok(DbStatus {
+            backend: self.backend().to_string(),
+            path: redact_url(&self.url),
+            schema_version: self.schema_version().await?,
+            latest_version: pg_latest_version(),
+            size_bytes,
+            timescale: Some(self.timescale),
+            ..Default::default()
+        })
+    }
+
+    async fn snapshot(&self, _path: &Path) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+}
+
+// runs against the database in `HISTORY_TEST_POSTGRES`, with
+// `cargo test -- --ignored`
+#[tokio::test]
+#[ignore = "needs a postgres database in HISTORY_TEST_POSTGRES"]
+async fn test_pg_store() -> anyhow::Result<()> {
+    let url = std::env::var("HISTORY_TEST_POSTGRES")
+        .map_err(|_| anyhow::anyhow!("HISTORY_TEST_POSTGRES is not set"))?;
+    let store = PgStore::connect(&url).await?;
+    // migrating twice is a no-op
+    store.migrate().await?;
+    assert_eq!(store.schema_version().await?, pg_latest_version());
+
+    let name = format!("test_pg_store_{}", Utc::now().timestamp_millis());
+    for timestamp in (0..3 * DAY).step_by(600) {
+        let rows = ["f01", "f02"]
+            .iter()
+            .map(|miner| HistoryRow {
+                name: name.clone(),
+                miner: miner.to_string(),
+                timestamp,
+                power: timestamp as f64,
+                blocks: 1,
+                ..Default::default()
+            })
+            .collect();
+        store.insert(rows).await?;
+    }
+
+    let (times, infos) = store.get(name.clone(), vec![], -1, HOUR).await?;
+    assert_eq!(times.len(), 6);
+    assert_eq!(infos[1].power, 1200.);
+    assert_eq!(infos[1].blocks, 2);
+
+    let (times, infos) = store
+        .aggregate(
+            name.clone(),
+            vec!["f01".to_string()],
+            -1,
+            3 * DAY,
+            Bucket::Day,
+            Aggregate::Avg,
+        )
+        .await?;
+    assert_eq!(times, vec![0, DAY, 2 * DAY]);
+    assert_eq!(infos[0].blocks, 1);
+
+    let retention = Retention {
+        raw: DAY / 2,
+        hourly: DAY + DAY / 2,
+        daily: 0,
+    };
+    let stats = store.compact(&name, &retention, 3 * DAY).await?;
+    assert_eq!(stats.raw, 2 * 60 * 6);
+    assert_eq!(stats.hourly, 2 * 24);
+
+    let (times, _) = store
+        .get(name.clone(), vec!["f01".to_string()], -1, 3 * DAY)
+        .await?;
+    assert_eq!(times.len(), 1 + 36 + 12 * 6);
+
+    let status = store.status().await?;
+    assert_eq!(status.backend, "postgres");
+
+    Ok(())
+}
+
+#[test]
+fn test_pg_sql() {
+    assert_eq!(
+        pg_sql("SELECT * FROM history WHERE name = ? AND miner IN (?,?)"),
+        "SELECT * FROM history WHERE name = $1 AND miner IN ($2,$3)"
+    );
+}
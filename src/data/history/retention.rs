@@ -0,0 +1,228 @@
+use chrono::Utc;
+use savefile_derive::Savefile;
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, SqlitePool};
+
+use super::{db::METRIC_COLUMNS, store::SharedStore, *};
+
+pub(crate) const HOUR: i64 = 3600;
+pub(crate) const DAY: i64 = 86400;
+
+lazy_static::lazy_static! {
+    // seconds between two compaction runs
+    pub static ref COMPACT_INTERVAL: u64 = std::env::var("HISTORY_COMPACT_INTERVAL")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(600);
+}
+
+// how long each tier of a history subscription is kept, in seconds,
+// 0 keeps the tier forever, e.g. raw 7 days, hourly 90 days, daily 0
+#[derive(Savefile, Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Retention {
+    // every sample
+    pub raw: i64,
+    // last sample of each hour, filled from raw rows past `raw`
+    pub hourly: i64,
+    // last sample of each day, filled from hourly rows past `hourly`
+    pub daily: i64,
+}
+
+impl Retention {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let tiers = [
+            ("raw", self.raw),
+            ("hourly", self.hourly),
+            ("daily", self.daily),
+        ];
+        for (name, keep) in tiers {
+            if keep < 0 {
+                anyhow::bail!("{} retention must not be negative", name);
+            }
+        }
+        // a coarser tier is filled from the finer one, so it has to outlive it
+        for pair in tiers.windows(2) {
+            let ((finer, f), (coarser, c)) = (pair[0], pair[1]);
+            if c != 0 && (f == 0 || c < f) {
+                anyhow::bail!("{} retention must not be shorter than {}", coarser, finer);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactStats {
+    // rows removed from each tier
+    pub raw: u64,
+    pub hourly: u64,
+    pub daily: u64,
+}
+
+// copy the last sample of each `size` bucket of `from` older than `before` into `to`,
+// binds name and before
+pub(crate) fn rollup_sql(from: &str, to: &str, size: i64) -> String {
+    let columns = METRIC_COLUMNS.join(", ");
+    let updates = METRIC_COLUMNS
+        .iter()
+        .map(|c| format!("{c} = excluded.{c}"))
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!(
+        "INSERT INTO {to} (name, miner, timestamp, {columns})
+        SELECT name, miner, bucket, {columns} FROM (
+            SELECT *, (timestamp / {size}) * {size} AS bucket,
+                ROW_NUMBER() OVER (PARTITION BY miner, timestamp / {size} ORDER BY timestamp DESC) AS rn
+            FROM {from} WHERE name = ? AND timestamp < ?
+        ) AS buckets WHERE rn = 1
+        ON CONFLICT (name, miner, timestamp) DO UPDATE SET {updates}"
+    )
+}
+
+// start of the raw and hourly ranges that are rolled up, aligned so
+// buckets are never split between two tiers
+pub(crate) fn cutoffs(retention: &Retention, now: i64) -> (i64, i64) {
+    (
+        (now - retention.raw).div_euclid(HOUR) * HOUR,
+        (now - retention.hourly).div_euclid(DAY) * DAY,
+    )
+}
+
+// move rows of subscription <name> past their tier's retention one tier down
+pub async fn compact(
+    conn: &SqlitePool,
+    name: &str,
+    retention: &Retention,
+    now: i64,
+) -> anyhow::Result<CompactStats> {
+    let mut stats = CompactStats::default();
+    let mut tx = conn.begin().await?;
+    let (raw_before, hourly_before) = cutoffs(retention, now);
+
+    if retention.raw > 0 {
+        let before = raw_before;
+        let sql = rollup_sql("history", "history_hourly", HOUR);
+        tx.execute(sqlx::query(&sql).bind(name).bind(before))
+            .await?;
+        stats.raw = tx
+            .execute(
+                sqlx::query("DELETE FROM history WHERE name = ? AND timestamp < ?")
+                    .bind(name)
+                    .bind(before),
+            )
+            .await?
+            .rows_affected();
+    }
+    if retention.hourly > 0 {
+        let before = hourly_before;
+        let sql = rollup_sql("history_hourly", "history_daily", DAY);
+        tx.execute(sqlx::query(&sql).bind(name).bind(before))
+            .await?;
+        stats.hourly = tx
+            .execute(
+                sqlx::query("DELETE FROM history_hourly WHERE name = ? AND timestamp < ?")
+                    .bind(name)
+                    .bind(before),
+            )
+            .await?
+            .rows_affected();
+    }
+    if retention.daily > 0 {
+        stats.daily = tx
+            .execute(
+                sqlx::query("DELETE FROM history_daily WHERE name = ? AND timestamp < ?")
+                    .bind(name)
+                    .bind(now - retention.daily),
+            )
+            .await?
+            .rows_affected();
+    }
+
+    tx.commit().await?;
+    Ok(stats)
+}
+
+// compact every subscription by its own policy
+pub async fn compact_all(store: &SharedStore) -> anyhow::Result<()> {
+    let now = Utc::now().timestamp();
+    for item in GLOBAL_HISTORY.get().await {
+        let stats = store.compact(&item.name, &item.retention, now).await?;
+        if stats != CompactStats::default() {
+            tracing::info!("compacted history {}: {:?}", item.name, stats);
+        }
+    }
+    Ok(())
+}
+
+// run compaction every `COMPACT_INTERVAL` seconds
+pub async fn compaction_updater(store: SharedStore) {
+    loop {
+        if let Err(e) = compact_all(&store).await {
+            tracing::error!("compact history error: {}", e)
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(*COMPACT_INTERVAL)).await;
+    }
+}
+
+#[test]
+fn test_retention_validate() {
+    let ok = |raw, hourly, daily| Retention { raw, hourly, daily }.validate().is_ok();
+
+    assert!(ok(0, 0, 0));
+    assert!(ok(7 * DAY, 90 * DAY, 0));
+    assert!(ok(7 * DAY, 0, 0));
+    assert!(!ok(-1, 0, 0));
+    assert!(!ok(0, 90 * DAY, 0));
+    assert!(!ok(7 * DAY, DAY, 0));
+    assert!(!ok(7 * DAY, 0, 365 * DAY));
+}
+
+#[tokio::test]
+async fn test_compact() -> anyhow::Result<()> {
+    use super::db::{get_db, insert_db, HistoryRow};
+
+    let db = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await?;
+    super::migrate::migrate(&db).await?;
+
+    // one sample every 10 minutes for 3 days
+    let now = 3 * DAY;
+    for timestamp in (0..now).step_by(600) {
+        let row = HistoryRow {
+            name: "test".to_string(),
+            miner: "f01".to_string(),
+            timestamp,
+            power: timestamp as f64,
+            ..Default::default()
+        };
+        insert_db(db.clone(), row).await?;
+    }
+
+    let retention = Retention {
+        raw: DAY / 2,
+        hourly: DAY + DAY / 2,
+        daily: 0,
+    };
+    let stats = compact(&db, "test", &retention, now).await?;
+    // raw before 2.5 days, hourly before day 1
+    assert_eq!(stats.raw, 60 * 6);
+    assert_eq!(stats.hourly, 24);
+
+    let (times, infos) = get_db(db.clone(), "test".to_string(), vec![], -1, now).await?;
+    // 1 daily, 36 hourly and 12 hours of raw samples
+    assert_eq!(times.len(), 1 + 36 + 12 * 6);
+    assert_eq!(times[0], 0);
+    assert_eq!(infos[0].power, (DAY - 600) as f64);
+    assert_eq!(times[1], DAY);
+    assert_eq!(infos[1].power, (DAY + HOUR - 600) as f64);
+
+    // running again moves nothing
+    assert_eq!(
+        compact(&db, "test", &retention, now).await?,
+        CompactStats::default()
+    );
+
+    Ok(())
+}
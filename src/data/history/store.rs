@@ -0,0 +1,204 @@
+use std::{path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+
+use crate::data::filfox::models::MinerInfo;
+
+use super::{
+    aggregate::{aggregate_db, Aggregate, Bucket},
+    db::{export_sql, get_db, init_history_db, insert_db_batch, HistoryRow, HISTORY_DB},
+    integrity::{db_status, startup_check, DbStatus},
+    postgres::PgStore,
+    retention::{compact, CompactStats, Retention},
+};
+
+// where history samples are kept, picked by the scheme of `HISTORY_DB`
+#[async_trait]
+pub trait HistoryStore: Send + Sync {
+    // "sqlite" or "postgres"
+    fn backend(&self) -> &'static str;
+
+    // all rows in one transaction, rows already stored for the same
+    // name, miner and timestamp are skipped, returns the rows inserted
+    async fn insert(&self, rows: Vec<HistoryRow>) -> anyhow::Result<u64>;
+
+    // see `get_db`
+    async fn get(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+    ) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)>;
+
+    // see `aggregate_db`
+    async fn aggregate(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+        bucket: Bucket,
+        aggregate: Aggregate,
+    ) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)>;
+
+    // send the rows of `export_sql` to `rows` one at a time, stops early
+    // when the receiver is dropped
+    async fn stream(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+        rows: mpsc::Sender<HistoryRow>,
+    ) -> anyhow::Result<()>;
+
+    // see `retention::compact`
+    async fn compact(
+        &self,
+        name: &str,
+        retention: &Retention,
+        now: i64,
+    ) -> anyhow::Result<CompactStats>;
+
+    async fn status(&self) -> anyhow::Result<DbStatus>;
+
+    // copy the database to the new file `path` without stopping writers,
+    // false when the backend has no file to copy, postgres is left to pg_dump
+    async fn snapshot(&self, path: &Path) -> anyhow::Result<bool>;
+}
+
+pub type SharedStore = Arc<dyn HistoryStore>;
+
+pub struct SqliteStore {
+    pub pool: SqlitePool,
+}
+
+#[async_trait]
+impl HistoryStore for SqliteStore {
+    fn backend(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn insert(&self, rows: Vec<HistoryRow>) -> anyhow::Result<u64> {
+        insert_db_batch(self.pool.clone(), rows).await
+    }
+
+    async fn get(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+    ) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)> {
+        get_db(self.pool.clone(), name, miners, from, to).await
+    }
+
+    async fn aggregate(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+        bucket: Bucket,
+        aggregate: Aggregate,
+    ) -> anyhow::Result<(Vec<i64>, Vec<MinerInfo>)> {
+        aggregate_db(self.pool.clone(), name, miners, from, to, bucket, aggregate).await
+    }
+
+    async fn stream(
+        &self,
+        name: String,
+        miners: Vec<String>,
+        from: i64,
+        to: i64,
+        rows: mpsc::Sender<HistoryRow>,
+    ) -> anyhow::Result<()> {
+        let sql = export_sql(miners.len());
+        let mut query = sqlx::query_as(&sql).bind(name).bind(from).bind(to);
+        for miner in miners {
+            query = query.bind(miner);
+        }
+
+        let mut stream = query.fetch(&self.pool);
+        while let Some(row) = stream.try_next().await? {
+            if rows.send(row).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn compact(
+        &self,
+        name: &str,
+        retention: &Retention,
+        now: i64,
+    ) -> anyhow::Result<CompactStats> {
+        compact(&self.pool, name, retention, now).await
+    }
+
+    async fn status(&self) -> anyhow::Result<DbStatus> {
+        db_status(&self.pool).await
+    }
+
+    async fn snapshot(&self, path: &Path) -> anyhow::Result<bool> {
+        // a consistent, compacted copy, taken in one read transaction
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy().to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(true)
+    }
+}
+
+pub fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+// hide the password of a database url in logs and the status api
+pub fn redact_url(url: &str) -> String {
+    let (scheme, rest) = match url.split_once("://") {
+        Some(parts) => parts,
+        None => return url.to_string(),
+    };
+    match rest.split_once('@') {
+        Some((credentials, host)) => {
+            let user = credentials.split(':').next().unwrap_or_default();
+            format!("{}://{}:***@{}", scheme, user, host)
+        }
+        None => url.to_string(),
+    }
+}
+
+// open the store `HISTORY_DB` points at, postgres urls go to postgres,
+// anything else is a sqlite file
+pub async fn open_history_store() -> anyhow::Result<SharedStore> {
+    if is_postgres_url(&HISTORY_DB) {
+        let store = PgStore::connect(&HISTORY_DB).await?;
+        return Ok(Arc::new(store));
+    }
+
+    let pool = init_history_db().await?;
+    // refuse to start on a damaged database
+    startup_check(&pool).await?;
+    Ok(Arc::new(SqliteStore { pool }))
+}
+
+#[test]
+fn test_redact_url() {
+    assert_eq!(
+        redact_url("postgres://monitor:secret@db:5432/history"),
+        "postgres://monitor:***@db:5432/history"
+    );
+    assert_eq!(
+        redact_url("postgres://db:5432/history"),
+        "postgres://db:5432/history"
+    );
+    assert_eq!(redact_url("history.db"), "history.db");
+    assert!(is_postgres_url("postgresql://db/history"));
+    assert!(!is_postgres_url("sqlite://history.db"));
+}
@@ -1,27 +1,45 @@
+// the version checks generated by `#[savefile_versions]` trip this lint
+#![allow(clippy::manual_range_contains)]
+
 use std::sync::Arc;
 
 use chrono::Utc;
 use lazy_static::lazy_static;
-use savefile::{load_file, save_file};
+use savefile::load_file;
 use savefile_derive::Savefile;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
+use crate::{
+    data::state::{is_missing, load_json, load_state, save_json},
+    settings::settings,
+};
+
+use super::retention::Retention;
+
 // history subscribe item
 #[derive(Savefile, Clone, Serialize, Deserialize, Debug)]
 pub struct HistoryItem {
     pub name: String,
     pub interval: i64,
     pub add_time: i64,
+    // subscriptions from before retention keep everything
+    #[savefile_versions = "1.."]
+    #[serde(default)]
+    pub retention: Retention,
+    // the user that added it, shared when none, history.bin predates users
+    #[savefile_ignore]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
 }
 
-// file dir to save locally
-const DEFAULT_HISTORY_FILE: &str = "history.bin";
+// version of the last history.bin, only read when converting it to json
+const HISTORY_VERSION: u32 = 1;
 lazy_static! {
+    // file dir to save locally
     pub static ref HISTORY_FILE: String = {
-        option_env!("HISTORY_FILE")
-            .unwrap_or(DEFAULT_HISTORY_FILE)
-            .to_string()
+        let settings = settings();
+        settings.state_path(&settings.history_file)
     };
 }
 
@@ -46,17 +64,32 @@ impl GlobalHistory {
         Ok(())
     }
 
-    pub async fn add(&self, name: String, interval: i64) -> anyhow::Result<()> {
+    pub async fn add(
+        &self,
+        name: String,
+        interval: i64,
+        retention: Retention,
+        owner: Option<String>,
+    ) -> anyhow::Result<()> {
+        retention.validate()?;
         let current_timestamp = Utc::now().timestamp();
 
         let item = HistoryItem {
             name,
             interval,
             add_time: current_timestamp,
+            retention,
+            owner,
         };
 
         {
-            self.history.write().await.push(item);
+            let mut items = self.history.write().await;
+            // rows are stored by name, two subscriptions cannot share one,
+            // the error does not say whose it is or that it exists at all
+            if items.iter().any(|i| i.name == item.name) {
+                anyhow::bail!("history subscription name {} is not available", item.name);
+            }
+            items.push(item);
         }
         {
             self.last_update.write().await.push(0);
@@ -94,6 +127,27 @@ impl GlobalHistory {
         Ok(())
     }
 
+    pub async fn set_retention(
+        &self,
+        name: &str,
+        retention: Retention,
+    ) -> anyhow::Result<HistoryItem> {
+        retention.validate()?;
+        let item = {
+            let mut items = self.history.write().await;
+            let item = items
+                .iter_mut()
+                .find(|i| i.name == name)
+                .ok_or_else(|| anyhow::anyhow!("history item not found!"))?;
+            item.retention = retention;
+            item.clone()
+        };
+
+        self.save().await?;
+
+        Ok(item)
+    }
+
     pub async fn get_history(&self, name: String) -> anyhow::Result<HistoryItem> {
         let items = self.get().await;
 
@@ -107,7 +161,7 @@ impl GlobalHistory {
     }
 }
 
-#[derive(Savefile)]
+#[derive(Savefile, Serialize, Deserialize)]
 pub struct History {
     // history
     pub history: Vec<HistoryItem>,
@@ -133,8 +187,13 @@ impl GlobalHistory {
 
     pub async fn save(&self) -> anyhow::Result<()> {
         let n: History = self.history().await;
-        save_config(&n);
-        Ok(())
+        save_config(&n)
+    }
+
+    // write the current state somewhere else than the state file, for backups
+    pub async fn save_to(&self, path: &str) -> anyhow::Result<()> {
+        let n: History = self.history().await;
+        save_json(path, &n)
     }
 
     fn load_config() -> anyhow::Result<GlobalHistory> {
@@ -154,20 +213,36 @@ lazy_static! {
     pub static ref GLOBAL_HISTORY: Arc<GlobalHistory> = {
         let config = match GlobalHistory::load_config() {
             Ok(c) => c,
-            Err(_) => GlobalHistory {
-                history: RwLock::new(vec![]),
-                last_update: RwLock::new(vec![]),
-            },
+            Err(e) => {
+                if !is_missing(&e) {
+                    tracing::error!("{:#}, starting without history subscriptions", e);
+                }
+                GlobalHistory {
+                    history: RwLock::new(vec![]),
+                    last_update: RwLock::new(vec![]),
+                }
+            }
         };
 
         Arc::new(config)
     };
 }
 
-fn save_config(config: &History) {
-    save_file(&*HISTORY_FILE, 0, config).unwrap();
+fn save_config(config: &History) -> anyhow::Result<()> {
+    save_json(&HISTORY_FILE, config)
 }
 
 fn load_config() -> anyhow::Result<History> {
-    Ok(load_file(&*HISTORY_FILE, 0)?)
+    load_state(&HISTORY_FILE, load_legacy_history)
+}
+
+// history.bin written by older builds
+pub fn load_legacy_history(path: &str) -> anyhow::Result<History> {
+    Ok(load_file(path, HISTORY_VERSION)?)
+}
+
+// fails when `path` is not a history.json this build can load
+pub fn check_history_file(path: &str) -> anyhow::Result<()> {
+    let _: History = load_json(path)?;
+    Ok(())
 }
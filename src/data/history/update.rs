@@ -1,36 +1,36 @@
 use chrono::Utc;
-use sqlx::SqlitePool;
 
-use crate::apis::info::get_info_handler;
+use crate::{apis::info::get_info_handler, data::users::GLOBAL_USERS};
 
-use super::{db::DealDbType, *};
+use super::{db::HistoryRow, store::SharedStore, *};
+
+const HISTORY_TICK: std::time::Duration = std::time::Duration::from_secs(1);
 
 // update history when global node info changes
-pub async fn update_history(conn: SqlitePool) -> anyhow::Result<()> {
+pub async fn update_history(store: &SharedStore) -> anyhow::Result<()> {
     let histories = GLOBAL_HISTORY.get().await;
     let last_updates = GLOBAL_HISTORY.last_update().await;
     let current_timestamp = Utc::now().timestamp();
 
     for ((idx, history), last) in histories.into_iter().enumerate().zip(last_updates) {
         if current_timestamp - last > history.interval {
-            // String, // 0    name
-            // i64,    // 1    timestamp
-            // f64,    // 2    pledge
-            // f64,    // 3    power
-            // i64,    // 4    blocks
-            // f64,    // 5    rewards
-            let info = get_info_handler().await?;
-            let data: DealDbType = (
-                history.name,
-                current_timestamp,
-                info.total.pledge,
-                info.total.power,
-                info.total.blocks as i64,
-                info.total.rewards,
-            );
-
-            // insert current item to db
-            super::db::insert_db(conn.clone(), data).await?;
+            // a user's subscription only records the nodes it watches
+            let nodes = match &history.owner {
+                Some(user) => Some(GLOBAL_USERS.watchlist(user).await),
+                None => None,
+            };
+            let info = get_info_handler(false, nodes.as_deref()).await?;
+            let data: Vec<HistoryRow> = info
+                .info
+                .iter()
+                // never fetched miners have no data to record, and a stale
+                // miner's last good data was recorded when it was fresh
+                .filter(|i| i.fetched_at.is_some() && !i.stale)
+                .map(|i| HistoryRow::new(&history.name, current_timestamp, i))
+                .collect();
+
+            // insert one row per miner to db
+            store.insert(data).await?;
             // update last update timestamp
             GLOBAL_HISTORY.update_time(idx, current_timestamp).await?;
         }
@@ -41,10 +41,12 @@ pub async fn update_history(conn: SqlitePool) -> anyhow::Result<()> {
     Ok(())
 }
 
-// pub async fn history_updater() {
-//     loop {
-//         if let Err(e) = update_history().await {
-//             tracing::error!("update_history error: {}", e)
-//         }
-//     }
-// }
+// check history subscriptions every `HISTORY_TICK`
+pub async fn history_updater(store: SharedStore) {
+    loop {
+        tokio::time::sleep(HISTORY_TICK).await;
+        if let Err(e) = update_history(&store).await {
+            tracing::error!("update_history error: {}", e)
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::{collections::BTreeSet, fmt, sync::Arc};
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::settings::settings;
+
+use super::{
+    state::{is_missing, load_json, load_or_recover, save_json},
+    users::check_user_name,
+};
+
+// keys are handed out as nm_<id>_<secret>, only their sha256 is kept
+const KEY_PREFIX: &str = "nm";
+const ID_BYTES: usize = 4;
+const SECRET_BYTES: usize = 32;
+
+lazy_static! {
+    // file dir to save locally
+    pub static ref KEYS_FILE: String = {
+        let settings = settings();
+        settings.state_path(&settings.keys_file)
+    };
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    // history, subscriptions, nodes and breakers
+    Read,
+    // add and delete subscriptions
+    WriteSubscriptions,
+    // everything, config, backups, imports and resets included
+    Admin,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scope::Read => "read",
+            Scope::WriteSubscriptions => "write-subscriptions",
+            Scope::Admin => "admin",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    // hex sha256 of the whole key
+    pub hash: String,
+    pub scopes: BTreeSet<Scope>,
+    // requests with the key only see this user's nodes and subscriptions,
+    // keys without one see everything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub created_at: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at: Option<i64>,
+}
+
+impl ApiKey {
+    // admin grants every scope
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.revoked_at.is_none()
+            && (self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Keys {
+    pub keys: Vec<ApiKey>,
+}
+
+pub(crate) fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+pub(crate) fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// compare hashes without leaking how much of them matched
+pub(crate) fn same_hash(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+impl Keys {
+    // no keys file yet is no keys
+    pub fn load() -> anyhow::Result<Keys> {
+        match load_or_recover(&KEYS_FILE) {
+            Err(e) if is_missing(&e) => Ok(Keys::default()),
+            res => res,
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        save_json(&KEYS_FILE, self)
+    }
+
+    // returns the new key, it cannot be shown again
+    pub fn create(
+        &mut self,
+        name: &str,
+        scopes: &[Scope],
+        user: Option<&str>,
+    ) -> anyhow::Result<(ApiKey, String)> {
+        if let Some(user) = user {
+            check_user_name(user)?;
+        }
+        if name.trim().is_empty() {
+            anyhow::bail!("a key needs a name");
+        }
+        if scopes.is_empty() {
+            anyhow::bail!("a key needs at least one scope");
+        }
+
+        let id = loop {
+            let id = random_hex(ID_BYTES);
+            if self.keys.iter().all(|k| k.id != id) {
+                break id;
+            }
+        };
+        let key = format!("{}_{}_{}", KEY_PREFIX, id, random_hex(SECRET_BYTES));
+        let api_key = ApiKey {
+            id,
+            name: name.trim().to_string(),
+            hash: hash_key(&key),
+            scopes: scopes.iter().copied().collect(),
+            user: user.map(str::to_string),
+            created_at: Utc::now().timestamp(),
+            revoked_at: None,
+        };
+        self.keys.push(api_key.clone());
+
+        Ok((api_key, key))
+    }
+
+    pub fn revoke(&mut self, id: &str) -> anyhow::Result<ApiKey> {
+        match self.keys.iter_mut().find(|k| k.id == id) {
+            Some(k) if k.revoked_at.is_some() => anyhow::bail!("key {} is already revoked", id),
+            Some(k) => {
+                k.revoked_at = Some(Utc::now().timestamp());
+                Ok(k.clone())
+            }
+            None => anyhow::bail!("no key {}", id),
+        }
+    }
+
+    // the unrevoked key a client sent, if any
+    pub fn find(&self, key: &str) -> Option<&ApiKey> {
+        let id = key
+            .strip_prefix(KEY_PREFIX)?
+            .strip_prefix('_')?
+            .split('_')
+            .next()?;
+        let hash = hash_key(key);
+        self.keys
+            .iter()
+            .find(|k| k.id == id && k.revoked_at.is_none() && same_hash(&k.hash, &hash))
+    }
+
+    pub fn active(&self) -> usize {
+        self.keys.iter().filter(|k| k.revoked_at.is_none()).count()
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut ids = BTreeSet::new();
+        for k in &self.keys {
+            if !ids.insert(&k.id) {
+                anyhow::bail!("duplicate key id {}", k.id);
+            }
+            if k.hash.len() != 64 || !k.hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                anyhow::bail!("key {} has no sha256 hash", k.id);
+            }
+            if k.scopes.is_empty() {
+                anyhow::bail!("key {} has no scopes", k.id);
+            }
+            if let Some(user) = &k.user {
+                check_user_name(user)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct GlobalKeys {
+    keys: RwLock<Keys>,
+}
+
+impl GlobalKeys {
+    pub async fn keys(&self) -> Keys {
+        self.keys.read().await.clone()
+    }
+
+    pub async fn find(&self, key: &str) -> Option<ApiKey> {
+        self.keys.read().await.find(key).cloned()
+    }
+
+    pub async fn active(&self) -> usize {
+        self.keys.read().await.active()
+    }
+
+    // read keys.json again after the cli changed it, returns what changed, an
+    // invalid file is rejected and the running keys left alone
+    pub async fn reload(&self) -> anyhow::Result<Vec<String>> {
+        let keys: Keys = load_json(&KEYS_FILE)?;
+        keys.validate()?;
+
+        let mut current = self.keys.write().await;
+        let mut changes = vec![];
+        for k in &keys.keys {
+            let state = match current.keys.iter().find(|c| c.id == k.id) {
+                None => "added",
+                Some(c) if c.revoked_at.is_none() && k.revoked_at.is_some() => "revoked",
+                Some(c) if c != k => "changed",
+                Some(_) => continue,
+            };
+            changes.push(format!("key {} ({}) {}", k.id, k.name, state));
+        }
+        for c in &current.keys {
+            if keys.keys.iter().all(|k| k.id != c.id) {
+                changes.push(format!("key {} ({}) removed", c.id, c.name));
+            }
+        }
+        *current = keys;
+
+        Ok(changes)
+    }
+}
+
+lazy_static! {
+    pub static ref GLOBAL_KEYS: Arc<GlobalKeys> = {
+        let keys = Keys::load().and_then(|keys| keys.validate().map(|_| keys));
+        let keys = keys.unwrap_or_else(|e| {
+            tracing::error!("{:#}, starting without api keys", e);
+            Keys::default()
+        });
+
+        Arc::new(GlobalKeys {
+            keys: RwLock::new(keys),
+        })
+    };
+}
+
+#[test]
+fn test_api_keys() -> anyhow::Result<()> {
+    let mut keys = Keys::default();
+    let (reader, read_key) = keys.create("dashboard", &[Scope::Read], Some("team-a"))?;
+    let (_, admin_key) = keys.create("ops", &[Scope::Admin], None)?;
+    assert!(keys.create(" ", &[Scope::Read], None).is_err());
+    assert!(keys.create("empty", &[], None).is_err());
+    assert!(keys
+        .create("bad user", &[Scope::Read], Some("a b"))
+        .is_err());
+
+    // only the hash is kept
+    assert!(read_key.starts_with(&format!("nm_{}_", reader.id)));
+    assert!(!serde_json::to_string(&keys)?.contains(&read_key));
+    keys.validate()?;
+
+    let found = keys.find(&read_key).unwrap();
+    assert!(found.allows(Scope::Read));
+    assert_eq!(found.user.as_deref(), Some("team-a"));
+    assert!(!found.allows(Scope::WriteSubscriptions));
+    assert!(keys
+        .find(&admin_key)
+        .unwrap()
+        .allows(Scope::WriteSubscriptions));
+    assert!(keys.find(&format!("{}0", read_key)).is_none());
+    assert!(keys.find("nm_").is_none());
+    assert!(keys.find("").is_none());
+
+    keys.revoke(&reader.id)?;
+    assert!(keys.find(&read_key).is_none());
+    assert!(keys.revoke(&reader.id).is_err());
+    assert_eq!(keys.active(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,404 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use chrono::Utc;
+use han_utils::crypto::verification_code::gen_number_verification_code;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::settings::{settings, LoginSettings};
+
+use super::{
+    keys::{hash_key, random_hex, same_hash, Scope},
+    mail::{configured_mailer, Mail, Mailer},
+    users::check_user_name,
+};
+
+const CODE_LENGTH: usize = 6;
+// seconds a code stays valid
+pub const CODE_TTL: i64 = 10 * 60;
+// wrong codes for one address within `LOCKOUT` before it is locked out
+const CODE_ATTEMPTS: u32 = 5;
+// seconds an address stays locked out, and wrong codes are remembered
+const LOCKOUT: i64 = 15 * 60;
+// seconds before another code may be asked for the same address
+const RESEND_AFTER: i64 = 60;
+// session tokens look like ns_<secret>, api keys like nm_<id>_<secret>
+const SESSION_PREFIX: &str = "ns_";
+const SESSION_BYTES: usize = 32;
+
+#[derive(Debug)]
+pub enum LoginError {
+    // no mail server or nobody allowed
+    Disabled,
+    InvalidEmail(String),
+    // seconds until another code may be asked for
+    TooSoon(i64),
+    // wrong, expired or never sent
+    BadCode,
+    TooManyAttempts,
+    Mail(anyhow::Error),
+}
+
+impl fmt::Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginError::Disabled => write!(f, "email login is not configured"),
+            LoginError::InvalidEmail(email) => write!(f, "{:?} is not an email address", email),
+            LoginError::TooSoon(secs) => write!(f, "a code was just sent, retry in {}s", secs),
+            LoginError::BadCode => write!(f, "wrong or expired code"),
+            LoginError::TooManyAttempts => write!(f, "too many wrong codes, request a new one"),
+            LoginError::Mail(e) => write!(f, "cannot send the code: {:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+// only the hash of a code is kept
+struct PendingCode {
+    hash: String,
+    expires_at: i64,
+}
+
+// kept per address, allowed or not, so it outlives the codes and answers
+// never tell which addresses may log in
+#[derive(Default)]
+struct Throttle {
+    requested_at: i64,
+    failures: u32,
+    failed_at: i64,
+    locked_until: i64,
+}
+
+impl Throttle {
+    fn active(&self, now: i64) -> bool {
+        self.requested_at + RESEND_AFTER > now
+            || self.locked_until > now
+            || (self.failures > 0 && self.failed_at + LOCKOUT > now)
+    }
+
+    // seconds until another code may be asked for
+    fn wait(&self, now: i64) -> i64 {
+        (self.requested_at + RESEND_AFTER).max(self.locked_until) - now
+    }
+
+    // returns whether the address is now locked out
+    fn fail(&mut self, now: i64) -> bool {
+        if self.failed_at + LOCKOUT <= now {
+            self.failures = 0;
+        }
+        self.failures += 1;
+        self.failed_at = now;
+        if self.failures >= CODE_ATTEMPTS {
+            self.failures = 0;
+            self.locked_until = now + LOCKOUT;
+            return true;
+        }
+        false
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    codes: HashMap<String, PendingCode>,
+    throttle: HashMap<String, Throttle>,
+}
+
+impl Pending {
+    fn expire(&mut self, now: i64) {
+        self.codes.retain(|_, c| c.expires_at > now);
+        self.throttle.retain(|_, t| t.active(now));
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    // start of the token hash, to tell sessions apart in logs
+    pub id: String,
+    // the email, sessions act as that user
+    pub user: String,
+    pub expires_at: i64,
+}
+
+impl Session {
+    // what the user's own api key could do, never admin
+    pub fn allows(&self, scope: Scope) -> bool {
+        scope != Scope::Admin
+    }
+}
+
+// passwordless login, a code mailed to an allowed address is exchanged for a
+// session token, codes and sessions are kept in memory so a restart logs
+// everyone out
+pub struct Login {
+    settings: LoginSettings,
+    mailer: Option<Arc<dyn Mailer>>,
+    pending: Mutex<Pending>,
+    // by token hash
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+fn normalize_email(email: &str) -> Result<String, LoginError> {
+    let email = email.trim().to_lowercase();
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && domain.contains('.') && !domain.contains('@'),
+        None => false,
+    };
+    if !valid || check_user_name(&email).is_err() {
+        return Err(LoginError::InvalidEmail(email));
+    }
+    Ok(email)
+}
+
+fn code_hash(email: &str, code: &str) -> String {
+    hash_key(&format!("{}:{}", email, code))
+}
+
+impl Login {
+    pub fn new(settings: LoginSettings, mailer: Option<Arc<dyn Mailer>>) -> Self {
+        Self {
+            settings,
+            mailer,
+            pending: Mutex::default(),
+            sessions: Mutex::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.mailer.is_some() && !self.settings.allow.is_empty()
+    }
+
+    // mail a code, addresses that may not log in get none, but the same
+    // answers, the resend wait and lockouts included
+    pub async fn request_code(&self, email: &str) -> Result<(), LoginError> {
+        let mailer = match &self.mailer {
+            Some(mailer) if self.enabled() => mailer,
+            _ => return Err(LoginError::Disabled),
+        };
+        let email = normalize_email(email)?;
+        let now = Utc::now().timestamp();
+        let code = gen_number_verification_code(CODE_LENGTH);
+        {
+            let mut pending = self.pending.lock().await;
+            pending.expire(now);
+            let throttle = pending.throttle.entry(email.clone()).or_default();
+            let wait = throttle.wait(now);
+            if wait > 0 {
+                return Err(LoginError::TooSoon(wait));
+            }
+            throttle.requested_at = now;
+
+            if !self.settings.allows(&email) {
+                tracing::info!("login code asked for {}, which may not log in", email);
+                return Ok(());
+            }
+            pending.codes.insert(
+                email.clone(),
+                PendingCode {
+                    hash: code_hash(&email, &code),
+                    expires_at: now + CODE_TTL,
+                },
+            );
+        }
+
+        let mail = Mail {
+            to: email.clone(),
+            subject: format!("node monitor login code {}", code),
+            body: format!(
+                "Your node monitor login code is {}.\nIt expires in {} minutes.\n",
+                code,
+                CODE_TTL / 60
+            ),
+        };
+        if let Err(e) = mailer.send(&mail).await {
+            self.pending.lock().await.codes.remove(&email);
+            return Err(LoginError::Mail(e));
+        }
+        tracing::info!("login code sent to {}", email);
+
+        Ok(())
+    }
+
+    // trade a mailed code for a session token, each code works once, wrong
+    // codes count against the address whether it has one pending or not
+    pub async fn verify(&self, email: &str, code: &str) -> Result<(String, Session), LoginError> {
+        if !self.enabled() {
+            return Err(LoginError::Disabled);
+        }
+        let email = normalize_email(email)?;
+        let now = Utc::now().timestamp();
+        {
+            let mut pending = self.pending.lock().await;
+            pending.expire(now);
+            let throttle = pending.throttle.entry(email.clone()).or_default();
+            if throttle.locked_until > now {
+                return Err(LoginError::TooManyAttempts);
+            }
+            let matches = match pending.codes.get(&email) {
+                Some(c) => same_hash(&c.hash, &code_hash(&email, code.trim())),
+                None => false,
+            };
+            if !matches {
+                let throttle = pending.throttle.entry(email.clone()).or_default();
+                if throttle.fail(now) {
+                    pending.codes.remove(&email);
+                    return Err(LoginError::TooManyAttempts);
+                }
+                return Err(LoginError::BadCode);
+            }
+            pending.codes.remove(&email);
+            if let Some(throttle) = pending.throttle.get_mut(&email) {
+                throttle.failures = 0;
+            }
+        }
+
+        let token = format!("{}{}", SESSION_PREFIX, random_hex(SESSION_BYTES));
+        let hash = hash_key(&token);
+        let session = Session {
+            id: hash[..8].to_string(),
+            user: email,
+            expires_at: now + i64::from(self.settings.session_hours) * 3600,
+        };
+        {
+            let mut sessions = self.sessions.lock().await;
+            sessions.retain(|_, s| s.expires_at > now);
+            sessions.insert(hash, session.clone());
+        }
+        tracing::info!("{} logged in, session {}", session.user, session.id);
+
+        Ok((token, session))
+    }
+
+    // the unexpired session of a token
+    pub async fn session(&self, token: &str) -> Option<Session> {
+        if !token.starts_with(SESSION_PREFIX) {
+            return None;
+        }
+        let now = Utc::now().timestamp();
+        let sessions = self.sessions.lock().await;
+        sessions
+            .get(&hash_key(token))
+            .filter(|s| s.expires_at > now)
+            .cloned()
+    }
+
+    pub async fn logout(&self, token: &str) -> bool {
+        self.sessions
+            .lock()
+            .await
+            .remove(&hash_key(token))
+            .is_some()
+    }
+}
+
+lazy_static! {
+    pub static ref GLOBAL_LOGIN: Arc<Login> = {
+        let settings = settings().login.clone();
+        let mailer = configured_mailer(&settings);
+        Arc::new(Login::new(settings, mailer))
+    };
+}
+
+#[tokio::test]
+async fn test_email_login() -> anyhow::Result<()> {
+    use super::mail::CaptureMailer;
+
+    let mailer = Arc::new(CaptureMailer::default());
+    let settings = LoginSettings {
+        allow: vec!["@example.com".to_string()],
+        ..Default::default()
+    };
+    let login = Login::new(settings, Some(mailer.clone()));
+    let last_code = || {
+        let sent = mailer.sent.lock().unwrap();
+        let body = &sent.last().unwrap().body;
+        body.chars()
+            .filter(|c| c.is_ascii_digit())
+            .take(CODE_LENGTH)
+            .collect::<String>()
+    };
+
+    assert!(matches!(
+        login.request_code("nobody").await,
+        Err(LoginError::InvalidEmail(_))
+    ));
+    // not allowed, same answers but nothing sent
+    login.request_code("eve@other.org").await?;
+    assert!(mailer.sent.lock().unwrap().is_empty());
+    assert!(matches!(
+        login.request_code("eve@other.org").await,
+        Err(LoginError::TooSoon(_))
+    ));
+
+    login.request_code(" Alice@Example.com ").await?;
+    let code = last_code();
+    assert_eq!(mailer.sent.lock().unwrap()[0].to, "alice@example.com");
+    assert!(matches!(
+        login.request_code("alice@example.com").await,
+        Err(LoginError::TooSoon(_))
+    ));
+
+    let wrong = if code == "000000" { "000001" } else { "000000" };
+    assert!(matches!(
+        login.verify("alice@example.com", wrong).await,
+        Err(LoginError::BadCode)
+    ));
+    let (token, session) = login.verify("alice@example.com", &code).await?;
+    assert_eq!(session.user, "alice@example.com");
+    assert!(session.allows(Scope::WriteSubscriptions));
+    assert!(!session.allows(Scope::Admin));
+    assert_eq!(login.session(&token).await.unwrap().id, session.id);
+    // a code works once
+    assert!(login.verify("alice@example.com", &code).await.is_err());
+
+    // guessing drops the code and locks the address, asking for a new code
+    // does not lift it
+    login.request_code("bob@example.com").await?;
+    let code = last_code();
+    let wrong = if code == "000000" { "000001" } else { "000000" };
+    for _ in 1..CODE_ATTEMPTS {
+        assert!(matches!(
+            login.verify("bob@example.com", wrong).await,
+            Err(LoginError::BadCode)
+        ));
+    }
+    assert!(matches!(
+        login.verify("bob@example.com", wrong).await,
+        Err(LoginError::TooManyAttempts)
+    ));
+    assert!(login.verify("bob@example.com", &code).await.is_err());
+    {
+        // past the resend wait, still locked
+        let mut pending = login.pending.lock().await;
+        pending
+            .throttle
+            .get_mut("bob@example.com")
+            .unwrap()
+            .requested_at -= RESEND_AFTER;
+    }
+    assert!(matches!(
+        login.request_code("bob@example.com").await,
+        Err(LoginError::TooSoon(wait)) if wait > RESEND_AFTER
+    ));
+
+    // addresses without a code lock out the same way
+    for _ in 1..CODE_ATTEMPTS {
+        assert!(login.verify("eve@other.org", "000000").await.is_err());
+    }
+    assert!(matches!(
+        login.verify("eve@other.org", "000000").await,
+        Err(LoginError::TooManyAttempts)
+    ));
+
+    assert!(login.logout(&token).await);
+    assert!(login.session(&token).await.is_none());
+
+    let off = Login::new(LoginSettings::default(), None);
+    assert!(matches!(
+        off.request_code("alice@example.com").await,
+        Err(LoginError::Disabled)
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::data::{
+    config::GLOBAL_CONFIG,
+    filfox::models::{ControlAddress, FilfoxMinerInfo, Miner, Owner, Sectors, Worker},
+    source::{url_host, MinerDataSource},
+};
+
+use super::{
+    models::{LotusMinerInfo, MinerActorState, MinerPower, MinerSectors},
+    rpc::LotusClient,
+};
+
+// miner data from our own lotus daemon
+pub struct LotusSource {
+    pub url: String,
+    pub token: Option<String>,
+}
+
+impl LotusSource {
+    pub fn new(url: &str, token: Option<String>) -> Self {
+        Self {
+            url: url.to_string(),
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl MinerDataSource for LotusSource {
+    fn name(&self) -> &str {
+        "lotus"
+    }
+
+    fn host(&self) -> String {
+        url_host(&self.url)
+    }
+
+    async fn miner_info(&self, id: &str) -> anyhow::Result<FilfoxMinerInfo> {
+        let client = LotusClient::new(
+            &self.url,
+            self.token.clone(),
+            GLOBAL_CONFIG.timeouts.lotus().await,
+        )?;
+
+        let power: MinerPower = client.call("StateMinerPower", json!([id, null])).await?;
+        let info: LotusMinerInfo = client.call("StateMinerInfo", json!([id, null])).await?;
+        let available: String = client
+            .call("StateMinerAvailableBalance", json!([id, null]))
+            .await?;
+        let sectors: MinerSectors = client
+            .call("StateMinerSectorCount", json!([id, null]))
+            .await?;
+
+        // the pledge the miner has locked, as kept in its actor state
+        let state: MinerActorState = client.call("StateReadState", json!([id, null])).await?;
+        let pledge = state.state.initial_pledge;
+        pledge
+            .parse::<u128>()
+            .with_context(|| format!("lotus: bad initial pledge {:?} for {}", pledge, id))?;
+
+        Ok(lotus_to_filfox(id, power, info, available, sectors, pledge))
+    }
+}
+
+// blocks mined and total rewards are not tracked by lotus and are left at zero
+fn lotus_to_filfox(
+    id: &str,
+    power: MinerPower,
+    info: LotusMinerInfo,
+    available: String,
+    sectors: MinerSectors,
+    pledge: String,
+) -> FilfoxMinerInfo {
+    let miner = Miner {
+        available_balance: available,
+        control_addresses: info
+            .control_addresses
+            .unwrap_or_default()
+            .into_iter()
+            .map(|address| ControlAddress {
+                address,
+                ..Default::default()
+            })
+            .collect(),
+        initial_pledge_requirement: pledge.clone(),
+        sector_pledge_balance: pledge,
+        multi_addresses: info.multiaddrs.unwrap_or_default(),
+        network_quality_adj_power: power.total_power.quality_adj_power,
+        network_raw_byte_power: power.total_power.raw_byte_power,
+        owner: Owner {
+            address: info.owner,
+            ..Default::default()
+        },
+        peer_id: info.peer_id.unwrap_or_default(),
+        quality_adj_power: power.miner_power.quality_adj_power,
+        raw_byte_power: power.miner_power.raw_byte_power,
+        sectors: Sectors {
+            active: sectors.active,
+            faulty: sectors.faulty,
+            live: sectors.live,
+            recovering: 0,
+        },
+        sector_size: info.sector_size,
+        worker: Worker {
+            address: info.worker,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    FilfoxMinerInfo {
+        actor: "storageminer".to_string(),
+        address: id.to_string(),
+        id: id.to_string(),
+        miner,
+        timestamp: chrono::Utc::now().timestamp(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+async fn mock_lotus_rpc() -> std::net::SocketAddr {
+    use axum::{http::HeaderMap, routing::post, Json, Router};
+    use serde_json::Value;
+
+    async fn handler(headers: HeaderMap, Json(req): Json<Value>) -> Json<Value> {
+        if headers.get("authorization").and_then(|h| h.to_str().ok()) != Some("Bearer secret") {
+            return Json(
+                json!({"jsonrpc": "2.0", "id": 1, "error": {"code": 1, "message": "unauthorized"}}),
+            );
+        }
+
+        let result = match req["method"].as_str().unwrap_or_default() {
+            "Filecoin.StateMinerPower" => json!({
+                "MinerPower": {"RawBytePower": "1099511627776", "QualityAdjPower": "10995116277760"},
+                "TotalPower": {"RawBytePower": "1", "QualityAdjPower": "2"},
+                "HasMinPower": true
+            }),
+            "Filecoin.StateMinerInfo" => json!({
+                "Owner": "f0100", "Worker": "f0101", "ControlAddresses": ["f0102"],
+                "PeerId": "12D3KooW", "Multiaddrs": null, "SectorSize": 34359738368_i64
+            }),
+            "Filecoin.StateMinerAvailableBalance" => json!("5000000000000000000"),
+            "Filecoin.StateMinerSectorCount" => json!({"Live": 10, "Active": 9, "Faulty": 1}),
+            "Filecoin.StateReadState" => json!({
+                "Balance": "7000000000000000000", "Code": {"/": "bafk2bzace"},
+                "State": {"InitialPledge": "2000000000000000000", "LockedFunds": "0"}
+            }),
+            m => {
+                return Json(
+                    json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32601, "message": m}}),
+                )
+            }
+        };
+
+        Json(json!({"jsonrpc": "2.0", "id": 1, "result": result}))
+    }
+
+    let app = Router::new().route("/rpc/v0", post(handler));
+    let server = axum::Server::bind(&([127, 0, 0, 1], 0).into()).serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+
+    addr
+}
+
+#[tokio::test]
+async fn test_lotus_miner_info() -> anyhow::Result<()> {
+    use crate::data::filfox::models::MinerInfo;
+
+    let addr = mock_lotus_rpc().await;
+    let source = LotusSource::new(
+        &format!("http://{}/rpc/v0", addr),
+        Some("secret".to_string()),
+    );
+
+    let info = source.miner_info("f01234").await?;
+    assert_eq!(info.id, "f01234");
+    assert_eq!(info.miner.owner.address, "f0100");
+    assert_eq!(info.miner.sectors.faulty, 1);
+
+    let info: MinerInfo = info.into();
+    assert_eq!(info.power, 10.);
+    assert_eq!(info.pledge, 2.);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lotus_unauthorized() -> anyhow::Result<()> {
+    let addr = mock_lotus_rpc().await;
+    let source = LotusSource::new(&format!("http://{}/rpc/v0", addr), None);
+
+    let err = source.miner_info("f01234").await.unwrap_err();
+    assert!(err.to_string().contains("unauthorized"));
+
+    Ok(())
+}
@@ -0,0 +1,3 @@
+pub mod miner_info;
+pub mod models;
+pub mod rpc;
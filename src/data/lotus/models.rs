@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+// results of the lotus `Filecoin.StateMiner*` rpc methods we poll
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct Claim {
+    pub raw_byte_power: String,
+    pub quality_adj_power: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinerPower {
+    pub miner_power: Claim,
+    pub total_power: Claim,
+    pub has_min_power: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct LotusMinerInfo {
+    pub owner: String,
+    pub worker: String,
+    pub control_addresses: Option<Vec<String>>,
+    pub peer_id: Option<String>,
+    pub multiaddrs: Option<Vec<String>>,
+    pub sector_size: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinerSectors {
+    pub live: i64,
+    pub active: i64,
+    pub faulty: i64,
+}
+
+// result of `StateReadState` on a miner actor, only the fields we read
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinerActorState {
+    pub state: MinerState,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinerState {
+    // attofil locked as pledge for the miner's sectors
+    pub initial_pledge: String,
+}
@@ -0,0 +1,76 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize)]
+struct RpcRequest<'a> {
+    jsonrpc: &'static str,
+    method: &'a str,
+    params: Value,
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+// minimal lotus json-rpc client
+pub struct LotusClient {
+    pub url: String,
+    pub token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl LotusClient {
+    pub fn new(url: &str, token: Option<String>, timeout: f32) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs_f32(timeout))
+            .build()?;
+
+        Ok(Self {
+            url: url.to_string(),
+            token,
+            client,
+        })
+    }
+
+    // call `Filecoin.<method>` with `params`
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> anyhow::Result<T> {
+        let method = format!("Filecoin.{}", method);
+        let req = RpcRequest {
+            jsonrpc: "2.0",
+            method: &method,
+            params,
+            id: 1,
+        };
+
+        let mut builder = self.client.post(&self.url).json(&req);
+        if let Some(token) = &self.token {
+            builder = builder.bearer_auth(token);
+        }
+
+        let res: RpcResponse<T> = builder.send().await?.error_for_status()?.json().await?;
+
+        match (res.result, res.error) {
+            (_, Some(e)) => Err(anyhow::anyhow!(
+                "{} failed: {} ({})",
+                method,
+                e.message,
+                e.code
+            )),
+            (Some(r), None) => Ok(r),
+            (None, None) => Err(anyhow::anyhow!("{} returned no result", method)),
+        }
+    }
+}
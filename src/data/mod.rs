@@ -1,4 +1,13 @@
+pub mod backup;
 pub mod config;
 pub mod filfox;
 pub mod history;
+pub mod keys;
+pub mod login;
+pub mod lotus;
+pub mod mail;
 pub mod nodes;
+pub mod reload;
+pub mod source;
+pub mod state;
+pub mod users;
@@ -1,33 +1,52 @@
+// the version checks generated by `#[savefile_versions]` trip this lint
+#![allow(clippy::manual_range_contains)]
+
 use std::sync::Arc;
 
 use lazy_static::lazy_static;
-use savefile::{load_file, save_file};
+use savefile::load_file;
 use savefile_derive::Savefile;
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-// file dir to save locally
-const DEFAULT_NODES_FILE: &str = "nodes.bin";
+use crate::settings::settings;
+
+use super::{
+    source::{check_source_names, SourcePreferences},
+    state::{diff_json, is_missing, load_json, load_state, save_json},
+    users::GLOBAL_USERS,
+};
+
+// version of the last nodes.bin, only read when converting it to json
+const NODES_VERSION: u32 = 1;
 lazy_static! {
+    // file dir to save locally
     pub static ref NODES_FILE: String = {
-        option_env!("NODES_FILE")
-            .unwrap_or(DEFAULT_NODES_FILE)
-            .to_string()
+        let settings = settings();
+        settings.state_path(&settings.nodes_file)
     };
 }
 
 // define data structure
 pub struct GlobalNodes {
     pub nodes: RwLock<Vec<String>>,
+    // ordered source names per node, nodes without one use the default order
+    pub sources: RwLock<SourcePreferences>,
 }
-#[derive(Savefile)]
+#[derive(Savefile, Serialize, Deserialize)]
 pub struct Nodes {
     // nodes
     pub nodes: Vec<String>,
+    // source preferences
+    #[savefile_versions = "1.."]
+    #[serde(default)]
+    pub sources: SourcePreferences,
 }
 impl From<Nodes> for GlobalNodes {
     fn from(n: Nodes) -> Self {
         Self {
             nodes: n.nodes.into(),
+            sources: n.sources.into(),
         }
     }
 }
@@ -36,13 +55,30 @@ impl GlobalNodes {
     pub async fn nodes(&self) -> Nodes {
         Nodes {
             nodes: self.nodes.read().await.clone(),
+            sources: self.sources.read().await.clone(),
+        }
+    }
+
+    // nodes.json and every user's watchlist, what the updater fetches
+    pub async fn polled(&self) -> Nodes {
+        let mut n = self.nodes().await;
+        for id in GLOBAL_USERS.watched().await {
+            if !n.nodes.contains(&id) {
+                n.nodes.push(id);
+            }
         }
+        n
     }
 
     pub async fn save(&self) -> anyhow::Result<()> {
         let n: Nodes = self.nodes().await;
-        save_config(&n);
-        Ok(())
+        save_config(&n)
+    }
+
+    // write the current state somewhere else than the state file, for backups
+    pub async fn save_to(&self, path: &str) -> anyhow::Result<()> {
+        let n: Nodes = self.nodes().await;
+        save_json(path, &n)
     }
 
     fn load_config() -> anyhow::Result<GlobalNodes> {
@@ -50,10 +86,29 @@ impl GlobalNodes {
         Ok(config)
     }
 
-    pub fn load(&mut self) -> anyhow::Result<()> {
-        let config: GlobalNodes = load_config()?.into();
-        *self = config;
+    // read nodes.json again after it was edited, returns what changed, an
+    // invalid file is rejected and the running nodes left alone
+    pub async fn reload(&self) -> anyhow::Result<Vec<String>> {
+        let n: Nodes = load_json(&NODES_FILE)?;
+        n.validate()?;
 
+        let changes = diff_json(&self.nodes().await, &n)?;
+        if !changes.is_empty() {
+            *self.nodes.write().await = n.nodes;
+            *self.sources.write().await = n.sources;
+        }
+        Ok(changes)
+    }
+}
+
+impl Nodes {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.nodes.iter().any(|id| id.trim().is_empty()) {
+            anyhow::bail!("empty node id");
+        }
+        for sources in self.sources.values() {
+            check_source_names(sources)?;
+        }
         Ok(())
     }
 }
@@ -62,19 +117,36 @@ lazy_static! {
     pub static ref GLOBAL_NODES: Arc<GlobalNodes> = {
         let config = match GlobalNodes::load_config() {
             Ok(c) => c,
-            Err(_) => GlobalNodes {
-                nodes: RwLock::new(vec![]),
-            },
+            Err(e) => {
+                if !is_missing(&e) {
+                    tracing::error!("{:#}, starting without nodes", e);
+                }
+                GlobalNodes {
+                    nodes: RwLock::new(vec![]),
+                    sources: RwLock::new(SourcePreferences::new()),
+                }
+            }
         };
 
         Arc::new(config)
     };
 }
 
-fn save_config(config: &Nodes) {
-    save_file(&*NODES_FILE, 0, config).unwrap();
+fn save_config(config: &Nodes) -> anyhow::Result<()> {
+    save_json(&NODES_FILE, config)
 }
 
 fn load_config() -> anyhow::Result<Nodes> {
-    Ok(load_file(&*NODES_FILE, 0)?)
+    load_state(&NODES_FILE, load_legacy_nodes)
+}
+
+// nodes.bin written by older builds
+pub fn load_legacy_nodes(path: &str) -> anyhow::Result<Nodes> {
+    Ok(load_file(path, NODES_VERSION)?)
+}
+
+// fails when `path` is not a nodes.json this build can load
+pub fn check_nodes_file(path: &str) -> anyhow::Result<()> {
+    let _: Nodes = load_json(path)?;
+    Ok(())
 }
@@ -0,0 +1,119 @@
+use std::{path::Path, time::SystemTime};
+
+use lazy_static::lazy_static;
+
+use super::{
+    config::{CONFIG_FILE, GLOBAL_CONFIG},
+    keys::{GLOBAL_KEYS, KEYS_FILE},
+    nodes::{GLOBAL_NODES, NODES_FILE},
+    state::is_missing,
+    users::{GLOBAL_USERS, USERS_FILE},
+};
+
+lazy_static! {
+    // seconds between checks of the state files for edits,
+    // 0 only reloads on SIGHUP
+    pub static ref CONFIG_RELOAD_INTERVAL: u64 = std::env::var("CONFIG_RELOAD_INTERVAL")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(2);
+}
+
+fn log_reload(file: &str, res: anyhow::Result<Vec<String>>) {
+    match res {
+        Ok(changes) => {
+            for change in changes {
+                tracing::info!("{} changed {}", file, change);
+            }
+        }
+        // nothing saved yet, the running state stays as it is
+        Err(e) if is_missing(&e) => tracing::debug!("{:#}", e),
+        Err(e) => tracing::error!("rejected {}, keeping the running state: {:#}", file, e),
+    }
+}
+
+// apply edits of config.json to the running server
+pub async fn reload_config() {
+    log_reload(&CONFIG_FILE, GLOBAL_CONFIG.reload().await);
+}
+
+// apply edits of nodes.json, node list and sources, to the running server
+pub async fn reload_nodes() {
+    log_reload(&NODES_FILE, GLOBAL_NODES.reload().await);
+}
+
+// pick up api keys created or revoked with `node-monitor keys`
+pub async fn reload_keys() {
+    log_reload(&KEYS_FILE, GLOBAL_KEYS.reload().await);
+}
+
+// apply edits of users.json, the watchlists
+pub async fn reload_users() {
+    log_reload(&USERS_FILE, GLOBAL_USERS.reload().await);
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(Path::new(path)).ok()?.modified().ok()
+}
+
+// reload on SIGHUP and whenever a state file's mtime moves, saves made by the
+// api reload to no changes
+pub async fn config_reloader() {
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::error!("cannot listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            tracing::info!(
+                "SIGHUP, reloading {}, {}, {} and {}",
+                *CONFIG_FILE,
+                *NODES_FILE,
+                *KEYS_FILE,
+                *USERS_FILE
+            );
+            reload_config().await;
+            reload_nodes().await;
+            reload_keys().await;
+            reload_users().await;
+        }
+    });
+
+    if *CONFIG_RELOAD_INTERVAL == 0 {
+        return;
+    }
+    let mut config = modified(&CONFIG_FILE);
+    let mut nodes = modified(&NODES_FILE);
+    let mut keys = modified(&KEYS_FILE);
+    let mut users = modified(&USERS_FILE);
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(*CONFIG_RELOAD_INTERVAL)).await;
+
+        let now = modified(&CONFIG_FILE);
+        if now != config {
+            config = now;
+            reload_config().await;
+        }
+        let now = modified(&NODES_FILE);
+        if now != nodes {
+            nodes = now;
+            reload_nodes().await;
+        }
+        let now = modified(&KEYS_FILE);
+        if now != keys {
+            keys = now;
+            reload_keys().await;
+        }
+        let now = modified(&USERS_FILE);
+        if now != users {
+            users = now;
+            reload_users().await;
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    // fetching normally
+    Closed,
+    // too many failures, fetches are skipped until the cooldown ends
+    Open,
+    // cooldown over, one trial fetch decides whether to close again
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitBreaker {
+    pub node: String,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    pub opened_at: Option<i64>,
+    // when an open breaker lets the next trial through
+    pub retry_at: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+impl CircuitBreaker {
+    pub fn new(node: &str) -> Self {
+        Self {
+            node: node.to_string(),
+            state: BreakerState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            retry_at: None,
+            last_error: None,
+        }
+    }
+
+    // whether a fetch may go out at `now`
+    pub fn allow(&mut self, now: i64) -> bool {
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::Open if self.retry_at.is_none_or(|t| now >= t) => {
+                self.state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open | BreakerState::HalfOpen => false,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.state = BreakerState::Closed;
+        self.consecutive_failures = 0;
+        self.opened_at = None;
+        self.retry_at = None;
+    }
+
+    pub fn record_failure(&mut self, error: String, threshold: u32, cooldown: f32, now: i64) {
+        self.consecutive_failures += 1;
+        self.last_error = Some(error);
+
+        if self.state == BreakerState::HalfOpen || self.consecutive_failures >= threshold.max(1) {
+            if self.state != BreakerState::Open {
+                tracing::warn!(
+                    "circuit breaker of {} opened after {} failures",
+                    self.node,
+                    self.consecutive_failures
+                );
+            }
+            self.state = BreakerState::Open;
+            self.opened_at = Some(now);
+            self.retry_at = Some(now + cooldown.max(0.) as i64);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Breakers {
+    pub breakers: RwLock<HashMap<String, CircuitBreaker>>,
+}
+
+impl Breakers {
+    pub async fn get(&self) -> Vec<CircuitBreaker> {
+        let mut out: Vec<CircuitBreaker> = self.breakers.read().await.values().cloned().collect();
+        out.sort_by(|a, b| a.node.cmp(&b.node));
+        out
+    }
+
+    pub async fn allow(&self, node: &str) -> bool {
+        let mut breakers = self.breakers.write().await;
+        breakers
+            .entry(node.to_string())
+            .or_insert_with(|| CircuitBreaker::new(node))
+            .allow(Utc::now().timestamp())
+    }
+
+    pub async fn record_success(&self, node: &str) {
+        let mut breakers = self.breakers.write().await;
+        breakers
+            .entry(node.to_string())
+            .or_insert_with(|| CircuitBreaker::new(node))
+            .record_success();
+    }
+
+    pub async fn record_failure(&self, node: &str, error: String, threshold: u32, cooldown: f32) {
+        let mut breakers = self.breakers.write().await;
+        breakers
+            .entry(node.to_string())
+            .or_insert_with(|| CircuitBreaker::new(node))
+            .record_failure(error, threshold, cooldown, Utc::now().timestamp());
+    }
+
+    // close the breakers of `nodes`
+    pub async fn reset(&self, nodes: &[String]) {
+        let mut breakers = self.breakers.write().await;
+        for node in nodes {
+            if let Some(b) = breakers.get_mut(node) {
+                b.record_success();
+            }
+        }
+    }
+
+    pub async fn retain(&self, nodes: &[String]) {
+        self.breakers
+            .write()
+            .await
+            .retain(|node, _| nodes.contains(node));
+    }
+}
+
+lazy_static! {
+    pub static ref GLOBAL_BREAKERS: Breakers = Breakers::default();
+}
+
+#[test]
+fn test_circuit_breaker() {
+    let mut breaker = CircuitBreaker::new("f01");
+
+    for _ in 0..3 {
+        assert!(breaker.allow(0));
+        breaker.record_failure("timeout".to_string(), 3, 60., 0);
+    }
+    assert_eq!(breaker.state, BreakerState::Open);
+    assert!(!breaker.allow(30));
+
+    // a failed trial opens it again
+    assert!(breaker.allow(60));
+    assert_eq!(breaker.state, BreakerState::HalfOpen);
+    assert!(!breaker.allow(60));
+    breaker.record_failure("timeout".to_string(), 3, 60., 60);
+    assert_eq!(breaker.retry_at, Some(120));
+
+    // a successful trial closes it
+    assert!(breaker.allow(120));
+    breaker.record_success();
+    assert_eq!(breaker.state, BreakerState::Closed);
+    assert_eq!(breaker.consecutive_failures, 0);
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+// weight of the newest result in the health score
+const SCORE_ALPHA: f64 = 0.2;
+// sources scoring below this are tried after the healthy ones
+pub const UNHEALTHY_SCORE: f64 = 0.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceHealth {
+    pub name: String,
+    /// exponentially weighted success rate, 1.0 is fully healthy
+    pub score: f64,
+    pub successes: u64,
+    pub failures: u64,
+    pub consecutive_failures: u64,
+    /// average latency of successful requests in milliseconds
+    pub latency_ms: f64,
+    pub last_success: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+impl SourceHealth {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            score: 1.,
+            successes: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            latency_ms: 0.,
+            last_success: None,
+            last_error: None,
+        }
+    }
+
+    pub fn healthy(&self) -> bool {
+        self.score >= UNHEALTHY_SCORE
+    }
+
+    fn record_success(&mut self, latency_ms: f64) {
+        self.score = self.score * (1. - SCORE_ALPHA) + SCORE_ALPHA;
+        self.latency_ms = if self.successes == 0 {
+            latency_ms
+        } else {
+            self.latency_ms * (1. - SCORE_ALPHA) + latency_ms * SCORE_ALPHA
+        };
+        self.successes += 1;
+        self.consecutive_failures = 0;
+        self.last_success = Some(Utc::now().timestamp());
+    }
+
+    fn record_failure(&mut self, error: String) {
+        self.score *= 1. - SCORE_ALPHA;
+        self.failures += 1;
+        self.consecutive_failures += 1;
+        self.last_error = Some(error);
+    }
+}
+
+#[derive(Default)]
+pub struct SourceHealths {
+    pub healths: RwLock<HashMap<String, SourceHealth>>,
+}
+
+impl SourceHealths {
+    pub async fn get(&self) -> Vec<SourceHealth> {
+        let mut out: Vec<SourceHealth> = self.healths.read().await.values().cloned().collect();
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+        out
+    }
+
+    pub async fn healthy(&self, name: &str) -> bool {
+        match self.healths.read().await.get(name) {
+            Some(h) => h.healthy(),
+            None => true,
+        }
+    }
+
+    pub async fn record_success(&self, name: &str, latency_ms: f64) {
+        let mut healths = self.healths.write().await;
+        healths
+            .entry(name.to_string())
+            .or_insert_with(|| SourceHealth::new(name))
+            .record_success(latency_ms);
+    }
+
+    pub async fn record_failure(&self, name: &str, error: String) {
+        let mut healths = self.healths.write().await;
+        healths
+            .entry(name.to_string())
+            .or_insert_with(|| SourceHealth::new(name))
+            .record_failure(error);
+    }
+}
+
+lazy_static! {
+    pub static ref GLOBAL_SOURCE_HEALTH: SourceHealths = SourceHealths::default();
+}
+
+#[test]
+fn test_source_health_score() {
+    let mut health = SourceHealth::new("test");
+    for _ in 0..4 {
+        health.record_failure("timeout".to_string());
+    }
+    assert!(!health.healthy());
+    assert_eq!(health.consecutive_failures, 4);
+
+    for _ in 0..4 {
+        health.record_success(100.);
+    }
+    assert!(health.healthy());
+    assert_eq!(health.consecutive_failures, 0);
+    assert_eq!(health.latency_ms, 100.);
+}
@@ -0,0 +1,325 @@
+pub mod breaker;
+pub mod health;
+pub mod rate_limit;
+pub mod retry;
+
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use self::{health::GLOBAL_SOURCE_HEALTH, rate_limit::HostRateLimiter, retry::retry};
+
+use super::{
+    config::GLOBAL_CONFIG,
+    filfox::{
+        miner_info::FilfoxSource,
+        models::{FilfoxMinerInfo, MinerRecord},
+    },
+    lotus::miner_info::LotusSource,
+};
+use crate::settings::settings;
+
+pub const KNOWN_SOURCES: [&str; 2] = ["filfox", "lotus"];
+
+/// A place miner data can be polled from, e.g. a block explorer or a lotus node.
+#[async_trait]
+pub trait MinerDataSource: Send + Sync {
+    /// short name of the source, used in logs and api responses
+    fn name(&self) -> &str;
+
+    /// upstream host, requests to the same host share a rate limit
+    fn host(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// fetch the current state of miner `id`
+    async fn miner_info(&self, id: &str) -> anyhow::Result<FilfoxMinerInfo>;
+}
+
+// `lotus_api` is where the lotus source polls, with the token from the settings
+pub fn source_by_name(name: &str, lotus_api: &str) -> anyhow::Result<Arc<dyn MinerDataSource>> {
+    match name {
+        "filfox" => Ok(Arc::new(FilfoxSource::default())),
+        "lotus" => Ok(Arc::new(LotusSource::new(
+            lotus_api,
+            settings().lotus.token.clone(),
+        ))),
+        _ => Err(anyhow::anyhow!("unknown miner source: {}", name)),
+    }
+}
+
+pub fn check_source_names(names: &[String]) -> anyhow::Result<()> {
+    for name in names {
+        if !KNOWN_SOURCES.contains(&name.as_str()) {
+            return Err(anyhow::anyhow!("unknown miner source: {}", name));
+        }
+    }
+    Ok(())
+}
+
+// config the sources of a registry were built from, (sources, lotus_api)
+type SourceConfig = (Vec<String>, String);
+
+// every source the poller may use, in default order
+pub struct SourceRegistry {
+    sources: RwLock<Vec<Arc<dyn MinerDataSource>>>,
+    // known sources outside the default order, for nodes that prefer them
+    others: RwLock<Vec<Arc<dyn MinerDataSource>>>,
+    // none when the sources were given rather than built from the config
+    built_from: RwLock<Option<SourceConfig>>,
+    limiter: HostRateLimiter,
+}
+
+impl SourceRegistry {
+    pub fn new(sources: Vec<Arc<dyn MinerDataSource>>) -> Self {
+        Self {
+            sources: sources.into(),
+            others: RwLock::new(vec![]),
+            built_from: RwLock::new(None),
+            limiter: HostRateLimiter::default(),
+        }
+    }
+
+    // every known source, the config's `sources` in their order first
+    pub async fn from_config() -> Self {
+        let built_from = (
+            GLOBAL_CONFIG.sources().await,
+            GLOBAL_CONFIG.lotus_api().await,
+        );
+        let (sources, others) = build_sources(&built_from);
+        let registry = Self::new(sources);
+        *registry.others.write().await = others;
+        *registry.built_from.write().await = Some(built_from);
+        registry
+    }
+
+    // rebuild the sources after the config changed them, the rate limits
+    // of each host carry over
+    pub async fn sync(&self) {
+        let mut built_from = self.built_from.write().await;
+        let current = match &*built_from {
+            Some(current) => current,
+            None => return,
+        };
+        let config = (
+            GLOBAL_CONFIG.sources().await,
+            GLOBAL_CONFIG.lotus_api().await,
+        );
+        if *current == config {
+            return;
+        }
+        tracing::info!(
+            "miner sources: {} -> {}",
+            current.0.join(","),
+            config.0.join(",")
+        );
+        let (sources, others) = build_sources(&config);
+        *self.sources.write().await = sources;
+        *self.others.write().await = others;
+        *built_from = Some(config);
+    }
+
+    pub async fn get(&self, name: &str) -> Option<Arc<dyn MinerDataSource>> {
+        let (sources, others) = (self.sources.read().await, self.others.read().await);
+        sources
+            .iter()
+            .chain(others.iter())
+            .find(|s| s.name() == name)
+            .cloned()
+    }
+
+    // sources to try for a node: its own preference if any, else the default order
+    pub async fn ordered(&self, preferred: Option<&Vec<String>>) -> Vec<Arc<dyn MinerDataSource>> {
+        match preferred {
+            Some(names) if !names.is_empty() => {
+                let mut sources = vec![];
+                for name in names {
+                    if let Some(source) = self.get(name).await {
+                        sources.push(source);
+                    }
+                }
+                sources
+            }
+            _ => self.sources.read().await.clone(),
+        }
+    }
+
+    // try `sources` in order, unhealthy sources last, until one answers
+    pub async fn fetch(
+        &self,
+        id: &str,
+        preferred: Option<&Vec<String>>,
+    ) -> anyhow::Result<MinerRecord> {
+        let sources = self.ordered(preferred).await;
+        if sources.is_empty() {
+            return Err(anyhow::anyhow!("no usable source for {}", id));
+        }
+
+        let mut healthy = vec![];
+        let mut unhealthy = vec![];
+        for source in sources {
+            if GLOBAL_SOURCE_HEALTH.healthy(source.name()).await {
+                healthy.push(source);
+            } else {
+                unhealthy.push(source);
+            }
+        }
+
+        let (rate, burst) = GLOBAL_CONFIG.rate_limit().await;
+        let policy = GLOBAL_CONFIG.retry_policy().await;
+        let mut errors = vec![];
+        for source in healthy.into_iter().chain(unhealthy) {
+            let start = Instant::now();
+            let res = retry(policy, || async {
+                self.limiter.acquire(&source.host(), rate, burst).await;
+                source.miner_info(id).await
+            })
+            .await;
+
+            match res {
+                Ok(info) => {
+                    let latency = start.elapsed().as_secs_f64() * 1000.;
+                    GLOBAL_SOURCE_HEALTH
+                        .record_success(source.name(), latency)
+                        .await;
+
+                    return Ok(MinerRecord::fresh(id, info, source.name()));
+                }
+                Err(e) => {
+                    tracing::warn!("fetch {} from {} failed: {}", id, source.name(), e);
+                    GLOBAL_SOURCE_HEALTH
+                        .record_failure(source.name(), e.to_string())
+                        .await;
+                    errors.push(format!("{}: {}", source.name(), e));
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "all sources failed for {}: {}",
+            id,
+            errors.join("; ")
+        ))
+    }
+}
+
+// (the sources in `names`, in order, every other known source), so a node
+// may prefer any known source whatever the default order
+type BuiltSources = (Vec<Arc<dyn MinerDataSource>>, Vec<Arc<dyn MinerDataSource>>);
+
+fn build_sources((names, lotus_api): &SourceConfig) -> BuiltSources {
+    let mut sources = vec![];
+    for name in names {
+        match source_by_name(name, lotus_api) {
+            Ok(s) => sources.push(s),
+            Err(e) => tracing::error!("{}", e),
+        }
+    }
+    if sources.is_empty() {
+        sources.push(Arc::new(FilfoxSource::default()) as Arc<dyn MinerDataSource>);
+    }
+    let mut others = vec![];
+    for name in KNOWN_SOURCES {
+        if !sources.iter().any(|s| s.name() == name) {
+            if let Ok(s) = source_by_name(name, lotus_api) {
+                others.push(s);
+            }
+        }
+    }
+    (sources, others)
+}
+
+// per node source preferences, keyed by node id
+pub type SourcePreferences = HashMap<String, Vec<String>>;
+
+// host part of `url`, falling back to the whole string
+pub fn url_host(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(u) => match (u.host_str(), u.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => url.to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+struct StaticSource {
+    name: String,
+    fail: bool,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl MinerDataSource for StaticSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn miner_info(&self, id: &str) -> anyhow::Result<FilfoxMinerInfo> {
+        if self.fail {
+            return Err(anyhow::anyhow!("timeout"));
+        }
+        Ok(FilfoxMinerInfo {
+            id: id.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_registry_failover() -> anyhow::Result<()> {
+    let registry = SourceRegistry::new(vec![
+        Arc::new(StaticSource {
+            name: "failover-down".to_string(),
+            fail: true,
+        }),
+        Arc::new(StaticSource {
+            name: "failover-up".to_string(),
+            fail: false,
+        }),
+    ]);
+
+    let record = registry.fetch("f01", None).await?;
+    assert_eq!(record.source.as_deref(), Some("failover-up"));
+    assert_eq!(record.info.id, "f01");
+
+    // a node preferring only the broken source fails
+    let preferred = vec!["failover-down".to_string()];
+    assert!(registry.fetch("f01", Some(&preferred)).await.is_err());
+
+    let healths = GLOBAL_SOURCE_HEALTH.get().await;
+    let down = healths.iter().find(|h| h.name == "failover-down").unwrap();
+    assert_eq!(down.consecutive_failures, 2);
+    assert!(down.score < 1.);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_known_sources() -> anyhow::Result<()> {
+    let (sources, others) = build_sources(&(
+        vec!["filfox".to_string()],
+        "http://127.0.0.1:1234/rpc/v0".to_string(),
+    ));
+    let registry = SourceRegistry::new(sources);
+    *registry.others.write().await = others;
+
+    // lotus is not in the default order, but a node may still prefer it
+    let names = |sources: Vec<Arc<dyn MinerDataSource>>| {
+        sources
+            .iter()
+            .map(|s| s.name().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(registry.ordered(None).await), vec!["filfox"]);
+    let preferred = vec!["lotus".to_string()];
+    assert_eq!(
+        names(registry.ordered(Some(&preferred)).await),
+        vec!["lotus"]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex;
+
+// token bucket refilled at `rate` tokens per second up to `burst`
+#[derive(Debug)]
+pub struct TokenBucket {
+    tokens: f32,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(burst: f32, now: Instant) -> Self {
+        Self {
+            tokens: burst,
+            last: now,
+        }
+    }
+
+    // take a token, or return how long to wait until one is available
+    pub fn try_take(&mut self, rate: f32, burst: f32, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * rate).min(burst.max(1.));
+        self.last = now;
+
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            return Ok(());
+        }
+        if rate <= 0. {
+            return Err(Duration::from_secs(1));
+        }
+        Err(Duration::from_secs_f32((1. - self.tokens) / rate))
+    }
+}
+
+// one token bucket per upstream host
+#[derive(Default)]
+pub struct HostRateLimiter {
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl HostRateLimiter {
+    // wait until a request to `host` is allowed
+    pub async fn acquire(&self, host: &str, rate: f32, burst: f32) {
+        loop {
+            let wait = {
+                let now = Instant::now();
+                let mut buckets = self.buckets.lock().await;
+                let bucket = buckets
+                    .entry(host.to_string())
+                    .or_insert_with(|| TokenBucket::new(burst, now));
+                match bucket.try_take(rate, burst, now) {
+                    Ok(()) => return,
+                    Err(wait) => wait,
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[test]
+fn test_token_bucket() {
+    let now = Instant::now();
+    let mut bucket = TokenBucket::new(2., now);
+
+    assert!(bucket.try_take(1., 2., now).is_ok());
+    assert!(bucket.try_take(1., 2., now).is_ok());
+    let wait = bucket.try_take(1., 2., now).unwrap_err();
+    assert!(wait <= Duration::from_secs(1));
+
+    // refilled after a second, but never above the burst
+    let later = now + Duration::from_secs(10);
+    assert!(bucket.try_take(1., 2., later).is_ok());
+    assert!(bucket.try_take(1., 2., later).is_ok());
+    assert!(bucket.try_take(1., 2., later).is_err());
+}
@@ -0,0 +1,105 @@
+use std::{future::Future, time::Duration};
+
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    // tries including the first one
+    pub attempts: u32,
+    // seconds before the first retry
+    pub backoff: f32,
+    // upper bound of a single backoff in seconds
+    pub backoff_max: f32,
+}
+
+impl RetryPolicy {
+    // delay before retry number `retry` (0 based): exponential with jitter
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exp = self.backoff.max(0.) * 2_f32.powi(retry.min(31) as i32);
+        let capped = exp.min(self.backoff_max.max(0.));
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5_f32);
+        Duration::from_secs_f32(capped * (0.5 + jitter))
+    }
+}
+
+// timeouts, connection errors, 429 and 5xx are worth retrying
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    for cause in e.chain() {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() || e.is_connect() {
+                return true;
+            }
+            if let Some(status) = e.status() {
+                return status.as_u16() == 429 || status.is_server_error();
+            }
+        }
+    }
+    false
+}
+
+// run `f` until it succeeds, fails with a permanent error or runs out of attempts
+pub async fn retry<T, F, Fut>(policy: RetryPolicy, mut f: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut retry = 0;
+    loop {
+        match f().await {
+            Ok(t) => return Ok(t),
+            Err(e) if retry + 1 < policy.attempts && is_transient(&e) => {
+                let delay = policy.delay(retry);
+                tracing::debug!("transient error, retrying in {:?}: {}", delay, e);
+                tokio::time::sleep(delay).await;
+                retry += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[test]
+fn test_retry_delay() {
+    let policy = RetryPolicy {
+        attempts: 5,
+        backoff: 1.,
+        backoff_max: 4.,
+    };
+
+    assert!(policy.delay(0) <= Duration::from_secs(1));
+    assert!(policy.delay(1) >= Duration::from_secs(1));
+    assert!(policy.delay(10) <= Duration::from_secs(4));
+}
+
+#[tokio::test]
+async fn test_retry_transient_only() -> anyhow::Result<()> {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let policy = RetryPolicy {
+        attempts: 3,
+        backoff: 0.,
+        backoff_max: 0.,
+    };
+
+    // nothing listens on port 1 so every try is a connection error
+    let tries = AtomicU32::new(0);
+    let res: anyhow::Result<()> = retry(policy, || async {
+        tries.fetch_add(1, Ordering::SeqCst);
+        reqwest::get("http://127.0.0.1:1").await?;
+        Ok(())
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(tries.load(Ordering::SeqCst), 3);
+
+    let tries = AtomicU32::new(0);
+    let res: anyhow::Result<()> = retry(policy, || async {
+        tries.fetch_add(1, Ordering::SeqCst);
+        Err(anyhow::anyhow!("miner not found"))
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(tries.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use std::{collections::BTreeSet, fs, io::Write, path::Path};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+// appended to a savefile once it has been converted to json
+const MIGRATED: &str = ".migrated";
+// the file as it was before the last save
+const BACKUP: &str = ".bak";
+// a save in progress, renamed over the file once complete
+const TEMP: &str = ".tmp";
+// an unreadable file, kept aside when it was recovered from its backup
+const CORRUPT: &str = ".corrupt";
+
+// state files are pretty json so they can be read and edited by hand
+pub fn save_json<T: Serialize>(path: &str, value: &T) -> anyhow::Result<()> {
+    let json = serde_json::to_vec_pretty(value)?;
+    write_atomic(path, &json).with_context(|| format!("failed to save {}", path))
+}
+
+pub fn load_json<T: DeserializeOwned>(path: &str) -> anyhow::Result<T> {
+    let json = fs::read(path).with_context(|| format!("failed to load {}", path))?;
+    serde_json::from_slice(&json).with_context(|| format!("failed to parse {}", path))
+}
+
+// no state saved yet, as opposed to state that cannot be read
+pub fn is_missing(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<std::io::Error>(), Some(e) if e.kind() == std::io::ErrorKind::NotFound)
+}
+
+// a crash or a full disk leaves either the old or the new file, never a cut one
+fn write_atomic(path: &str, bytes: &[u8]) -> anyhow::Result<()> {
+    let temp = format!("{}{}", path, TEMP);
+    let written = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(bytes)?;
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+
+    if Path::new(path).exists() {
+        fs::copy(path, format!("{}{}", path, BACKUP))?;
+    }
+    fs::rename(&temp, path)?;
+
+    // make the rename itself durable
+    let dir = match Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+// load `path`, or the backup of the last save when `path` is missing or
+// unreadable, a recovered backup is saved back and the bad file kept aside
+pub fn load_or_recover<T: Serialize + DeserializeOwned>(path: &str) -> anyhow::Result<T> {
+    let error = match load_json(path) {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+    let backup = format!("{}{}", path, BACKUP);
+    let value: T = match load_json(&backup) {
+        Ok(value) => value,
+        Err(_) => return Err(error),
+    };
+
+    tracing::warn!("{:#}, recovered it from {}", error, backup);
+    if Path::new(path).exists() {
+        fs::rename(path, format!("{}{}", path, CORRUPT))?;
+    }
+    save_json(path, &value)?;
+
+    Ok(value)
+}
+
+// "key: old -> new" for every value that differs, nested keys joined by dots
+pub fn diff_json<T: Serialize>(old: &T, new: &T) -> anyhow::Result<Vec<String>> {
+    let mut changes = vec![];
+    diff_value(
+        "",
+        &serde_json::to_value(old)?,
+        &serde_json::to_value(new)?,
+        &mut changes,
+    );
+    Ok(changes)
+}
+
+fn diff_value(path: &str, old: &Value, new: &Value, changes: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+            for key in keys {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                let (o, n) = (old.get(key), new.get(key));
+                diff_value(
+                    &path,
+                    o.unwrap_or(&Value::Null),
+                    n.unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ if old != new => changes.push(format!("{}: {} -> {}", path, old, new)),
+        _ => {}
+    }
+}
+
+// savefile written by builds before the state was json, config.json -> config.bin
+pub fn legacy_path(path: &str) -> String {
+    Path::new(path)
+        .with_extension("bin")
+        .to_string_lossy()
+        .to_string()
+}
+
+// load the json state at `path`, on the first start after the upgrade the
+// savefile next to it is converted and renamed to `<file>.bin.migrated`
+pub fn load_state<T, F>(path: &str, load_legacy: F) -> anyhow::Result<T>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce(&str) -> anyhow::Result<T>,
+{
+    let legacy = legacy_path(path);
+    let saved = Path::new(path).exists() || Path::new(&format!("{}{}", path, BACKUP)).exists();
+    if saved || legacy == path || !Path::new(&legacy).exists() {
+        return load_or_recover(path);
+    }
+
+    let value = load_legacy(&legacy)?;
+    save_json(path, &value)?;
+    fs::rename(&legacy, format!("{}{}", legacy, MIGRATED))?;
+    tracing::info!("converted {} to {}", legacy, path);
+
+    Ok(value)
+}
+
+#[test]
+fn test_load_state() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("node-monitor-test-state-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("nodes.json").to_string_lossy().to_string();
+    let legacy = legacy_path(&path);
+    assert!(legacy.ends_with("nodes.bin"));
+
+    let load_legacy = |p: &str| -> anyhow::Result<Vec<String>> { Ok(savefile::load_file(p, 0)?) };
+    assert!(load_state(&path, load_legacy).is_err());
+
+    savefile::save_file(&legacy, 0, &vec!["f01".to_string()])?;
+    assert_eq!(load_state(&path, load_legacy)?, vec!["f01".to_string()]);
+    assert!(!Path::new(&legacy).exists());
+    assert!(Path::new(&format!("{}{}", legacy, MIGRATED)).exists());
+
+    // later starts read the json, which may have been edited
+    save_json(&path, &vec!["f02".to_string()])?;
+    assert_eq!(load_state(&path, load_legacy)?, vec!["f02".to_string()]);
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_save_json_recover() -> anyhow::Result<()> {
+    let dir =
+        std::env::temp_dir().join(format!("node-monitor-test-recover-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("config.json").to_string_lossy().to_string();
+    let no_legacy = |_: &str| -> anyhow::Result<Vec<i64>> { unreachable!() };
+
+    let e = load_state(&path, no_legacy).unwrap_err();
+    assert!(is_missing(&e));
+
+    save_json(&path, &vec![1])?;
+    save_json(&path, &vec![2])?;
+    assert!(!Path::new(&format!("{}{}", path, TEMP)).exists());
+    assert_eq!(
+        load_json::<Vec<i64>>(&format!("{}{}", path, BACKUP))?,
+        vec![1]
+    );
+
+    // a cut write falls back to the previous save
+    fs::write(&path, "[3")?;
+    let e = load_json::<Vec<i64>>(&path).unwrap_err();
+    assert!(!is_missing(&e));
+    assert_eq!(load_state(&path, no_legacy)?, vec![1]);
+    assert_eq!(load_json::<Vec<i64>>(&path)?, vec![1]);
+    assert_eq!(fs::read_to_string(format!("{}{}", path, CORRUPT))?, "[3");
+
+    // saving into a missing directory is an error, not a panic
+    let missing = dir.join("missing").join("config.json");
+    assert!(save_json(&missing.to_string_lossy(), &vec![1]).is_err());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_diff_json() -> anyhow::Result<()> {
+    let old = serde_json::json!({"interval": 10.0, "timeouts": {"lotus": 10.0}, "nodes": ["f01"]});
+    let new =
+        serde_json::json!({"interval": 10.0, "timeouts": {"lotus": 5.0}, "nodes": ["f01", "f02"]});
+
+    assert_eq!(
+        diff_json(&old, &new)?,
+        vec![
+            r#"nodes: ["f01"] -> ["f01","f02"]"#,
+            "timeouts.lotus: 10.0 -> 5.0",
+        ]
+    );
+    assert!(diff_json(&old, &old)?.is_empty());
+
+    Ok(())
+}
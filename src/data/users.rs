@@ -0,0 +1,174 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::settings::settings;
+
+use super::state::{diff_json, is_missing, load_json, load_or_recover, save_json};
+
+lazy_static! {
+    // file dir to save locally
+    pub static ref USERS_FILE: String = {
+        let settings = settings();
+        settings.state_path(&settings.users_file)
+    };
+}
+
+// a user is named by its api keys, only what it keeps is saved here
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct User {
+    // watched nodes, polled along with nodes.json
+    pub nodes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Users {
+    pub users: BTreeMap<String, User>,
+}
+
+impl Users {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, user) in &self.users {
+            check_user_name(name)?;
+            if user.nodes.iter().any(|id| id.trim().is_empty()) {
+                anyhow::bail!("empty node id in the watchlist of {}", name);
+            }
+        }
+        Ok(())
+    }
+
+    // every node some user watches
+    pub fn watched(&self) -> BTreeSet<String> {
+        self.users
+            .values()
+            .flat_map(|u| u.nodes.iter().cloned())
+            .collect()
+    }
+}
+
+pub fn check_user_name(name: &str) -> anyhow::Result<()> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || "-_.@".contains(c);
+    if name.is_empty() || !name.chars().all(valid) {
+        anyhow::bail!(
+            "user name {:?} may only hold letters, digits and -_.@",
+            name
+        );
+    }
+    Ok(())
+}
+
+pub struct GlobalUsers {
+    users: RwLock<Users>,
+}
+
+impl GlobalUsers {
+    pub async fn users(&self) -> Users {
+        self.users.read().await.clone()
+    }
+
+    pub async fn watchlist(&self, user: &str) -> Vec<String> {
+        match self.users.read().await.users.get(user) {
+            Some(u) => u.nodes.clone(),
+            None => vec![],
+        }
+    }
+
+    pub async fn watched(&self) -> BTreeSet<String> {
+        self.users.read().await.watched()
+    }
+
+    pub async fn watch(&self, user: &str, id: String) -> anyhow::Result<Vec<String>> {
+        check_user_name(user)?;
+        let mut users = self.users.write().await;
+        let nodes = &mut users.users.entry(user.to_string()).or_default().nodes;
+        if !nodes.contains(&id) {
+            nodes.push(id);
+        }
+        let nodes = nodes.clone();
+        save_json(&USERS_FILE, &*users)?;
+
+        Ok(nodes)
+    }
+
+    pub async fn unwatch(&self, user: &str, ids: &[String]) -> anyhow::Result<Vec<String>> {
+        let mut users = self.users.write().await;
+        let nodes = match users.users.get_mut(user) {
+            Some(u) => {
+                u.nodes.retain(|id| !ids.contains(id));
+                u.nodes.clone()
+            }
+            None => return Ok(vec![]),
+        };
+        save_json(&USERS_FILE, &*users)?;
+
+        Ok(nodes)
+    }
+
+    // write the current state somewhere else than the state file, for backups
+    pub async fn save_to(&self, path: &str) -> anyhow::Result<()> {
+        save_json(path, &*self.users.read().await)
+    }
+
+    // read users.json again after it was edited, returns what changed, an
+    // invalid file is rejected and the running watchlists left alone
+    pub async fn reload(&self) -> anyhow::Result<Vec<String>> {
+        let users: Users = load_json(&USERS_FILE)?;
+        users.validate()?;
+
+        let mut current = self.users.write().await;
+        let changes = diff_json(&*current, &users)?;
+        *current = users;
+
+        Ok(changes)
+    }
+}
+
+// fails when `path` is not a users.json this build can load
+pub fn check_users_file(path: &str) -> anyhow::Result<()> {
+    let users: Users = load_json(path)?;
+    users.validate()
+}
+
+lazy_static! {
+    pub static ref GLOBAL_USERS: Arc<GlobalUsers> = {
+        let users = match load_or_recover::<Users>(&USERS_FILE) {
+            Ok(users) => users.validate().map(|_| users),
+            Err(e) => Err(e),
+        };
+        let users = users.unwrap_or_else(|e| {
+            if !is_missing(&e) {
+                tracing::error!("{:#}, starting without watchlists", e);
+            }
+            Users::default()
+        });
+
+        Arc::new(GlobalUsers {
+            users: RwLock::new(users),
+        })
+    };
+}
+
+#[test]
+fn test_users() -> anyhow::Result<()> {
+    let users: Users = serde_json::from_str(
+        r#"{"users": {"alice": {"nodes": ["f01", "f02"]}, "bob": {"nodes": ["f02", "f03"]}}}"#,
+    )?;
+    users.validate()?;
+    let watched: Vec<String> = users.watched().into_iter().collect();
+    assert_eq!(watched, vec!["f01", "f02", "f03"]);
+
+    assert!(check_user_name("team-a@example.com").is_ok());
+    assert!(check_user_name("").is_err());
+    assert!(check_user_name("a b").is_err());
+    let bad: Users = serde_json::from_str(r#"{"users": {"a/b": {}}}"#)?;
+    assert!(bad.validate().is_err());
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
 pub mod apis;
+pub mod cli;
 pub mod data;
 pub mod router;
+pub mod settings;
@@ -1,7 +1,9 @@
-use axum::Extension;
+use clap::Parser;
 use node_monitor::{
-    data::{filfox::update::miner_info_updater, history::db::init_history_db},
+    cli::{self, Cli, Command},
+    data::{history::db::HistoryDbError, reload},
     router,
+    settings::{self, settings, Settings},
 };
 
 #[static_init::dynamic]
@@ -12,24 +14,49 @@ static STATIC_HANDLER: () = {
         std::env::set_var("RUST_LOG", "debug");
     }
 
-    tracing_subscriber::fmt::init();
+    // stdout is left to commands like `export`
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
 };
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    // before anything reads a state file
+    settings::init(Settings::load(&cli.settings)?)?;
+    if cli.print_config {
+        return cli::print_config().await;
+    }
+
+    match cli.command {
+        None | Some(Command::Serve) => serve().await,
+        Some(Command::Export(args)) => cli::export_history(args).await,
+        Some(Command::Import(args)) => cli::import_history(args).await,
+        Some(Command::Backup(args)) => cli::backup(args).await,
+        Some(Command::Restore(args)) => cli::restore(args).await,
+        Some(Command::Keys(command)) => cli::keys(command).await,
+    }
+}
+
+async fn serve() -> anyhow::Result<()> {
+    let address = settings().address()?;
+    // outside the loop, a restarted router must not watch twice
+    tokio::spawn(reload::config_reloader());
     loop {
-        if let Err(e) = run().await {
+        if let Err(e) = run(address).await {
+            // restarting cannot fix the database, stop instead of looping
+            if e.downcast_ref::<HistoryDbError>().is_some() {
+                tracing::error!("{}", e);
+                return Err(e);
+            }
             tracing::error!("mail server error: {}", e.to_string())
         }
     }
 }
 
-async fn run() -> anyhow::Result<()> {
-    let port = std::env::var("PORT")
-        .expect("Port must be given!")
-        .parse::<u16>()
-        .expect("Port must be a number!");
-    let address = std::net::SocketAddr::from((std::net::Ipv4Addr::UNSPECIFIED, port));
+async fn run(address: std::net::SocketAddr) -> anyhow::Result<()> {
     tracing::info!("Running on: {}", address);
 
     let app = router::init_router().await?;
@@ -1,114 +1,206 @@
-use std::sync::Arc;
-
 use axum::{
-    routing::{on, post, MethodFilter},
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, on, post, MethodFilter},
     Extension, Router,
 };
 
 use crate::{
     apis,
-    data::{filfox::update::miner_info_updater, history::db::init_history_db},
+    data::{
+        filfox::update::miner_info_updater,
+        history::store::open_history_store,
+        keys::{Scope, GLOBAL_KEYS},
+        login::GLOBAL_LOGIN,
+    },
+    settings::settings,
 };
 
 pub async fn init_router() -> anyhow::Result<Router> {
-    use http::Method;
-    use tower_http::cors::{Any, CorsLayer};
+    use http::{header, header::HeaderName, HeaderValue, Method};
+    use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
-    let cors = CorsLayer::new()
-        // allow `GET` and `POST` when accessing the resource
-        .allow_methods([Method::GET, Method::POST])
+    let origins = &settings().cors_origins;
+    let allow_origin = match origins.is_empty() {
         // allow requests from any origin
-        .allow_origin(Any);
+        true => AllowOrigin::from(Any),
+        false => AllowOrigin::list(
+            origins
+                .iter()
+                .map(|o| o.parse::<HeaderValue>())
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::PATCH])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::IF_MATCH,
+            HeaderName::from_static(apis::auth::API_KEY_HEADER),
+        ])
+        .expose_headers([header::ETAG])
+        .allow_origin(allow_origin);
+
+    if GLOBAL_KEYS.active().await == 0 && !GLOBAL_LOGIN.enabled() {
+        tracing::warn!("no api keys, every request is refused, create one with `keys create`");
+    }
+    // every route sits in a group asking for one scope
+    let scope = |scope: Scope| middleware::from_fn_with_state(scope, apis::auth::require_scope);
 
     // init history db
-    let db = init_history_db().await?;
+    let db = open_history_store().await?;
     // start miner info updater
     let db_clone = db.clone();
     tokio::spawn(async move { miner_info_updater(db_clone).await });
 
-    let db_arc = Arc::new(db);
-
-    // .route("/",
-    // on(
-    //     MethodFilter::POST,
-    //     apis::history::post::post_history,
-    // ).on(
-    //     MethodFilter::GET,
-    //     apis::history::get::get_history,
-    // ))
-
-    let app = Router::new()
+    let history = Router::new()
+        .route("/", post(apis::history::post::post_history))
+        .route("/", on(MethodFilter::GET, apis::history::get::get_history))
+        .route(
+            "/aggregate",
+            post(apis::history::aggregate::post_history_aggregate),
+        )
+        .route("/export", get(apis::history::export::get_history_export))
+        .route(
+            "/retention",
+            get(apis::history::retention::get_history_retention),
+        )
+        .route_layer(scope(Scope::Read))
+        .merge(
+            Router::new()
+                .route(
+                    "/import",
+                    post(apis::history::import::post_history_import).layer(DefaultBodyLimit::max(
+                        apis::history::import::IMPORT_BODY_LIMIT,
+                    )),
+                )
+                .route(
+                    "/retention",
+                    post(apis::history::retention::post_history_retention),
+                )
+                .route_layer(scope(Scope::Admin)),
+        )
         .nest(
-            "/api",
+            "/subscribe",
             Router::new()
-                .nest(
-                    "/history",
-                    Router::new()
-                    .route("/", post(apis::history::post::post_history))
-                    .route(
-                        "/",
-                        on(MethodFilter::GET,  apis::history::get::get_history),
-                    )
-                   .nest(
-                        "/subscribe",
-                        Router::new()
-                            .route(
-                                "/add",
-                                on(
-                                    MethodFilter::POST,
-                                    apis::history::subscribe::add::post_history_subscribe_add,
-                                ),
-                            )
-                            .route(
-                                "/delete",
-                                on(
-                                    MethodFilter::POST,
-                                    apis::history::subscribe::delete::post_history_subscribe_delete,
-                                ),
-                            )
-                            .route(
-                                "/",
-                                on(
-                                    MethodFilter::GET,
-                                    apis::history::subscribe::get_history_subscribe,
-                                ),
-                            ),
+                .route(
+                    "/",
+                    on(
+                        MethodFilter::GET,
+                        apis::history::subscribe::get_history_subscribe,
                     ),
                 )
-                .nest(
-                    "/subscribe",
+                .route_layer(scope(Scope::Read))
+                .merge(
                     Router::new()
-                        .route("/", on(MethodFilter::GET, apis::subscribe::get_subscribe))
                         .route(
                             "/add",
-                            on(MethodFilter::POST, apis::subscribe::add::post_subscribe_add),
+                            on(
+                                MethodFilter::POST,
+                                apis::history::subscribe::add::post_history_subscribe_add,
+                            ),
                         )
                         .route(
                             "/delete",
                             on(
                                 MethodFilter::POST,
-                                apis::subscribe::delete::post_subscribe_delete,
+                                apis::history::subscribe::delete::post_history_subscribe_delete,
                             ),
-                        ),
+                        )
+                        .route_layer(scope(Scope::WriteSubscriptions)),
+                ),
+        );
+
+    let subscribe = Router::new()
+        .route("/", on(MethodFilter::GET, apis::subscribe::get_subscribe))
+        .route_layer(scope(Scope::Read))
+        .merge(
+            Router::new()
+                .route(
+                    "/add",
+                    on(MethodFilter::POST, apis::subscribe::add::post_subscribe_add),
                 )
+                .route(
+                    "/delete",
+                    on(
+                        MethodFilter::POST,
+                        apis::subscribe::delete::post_subscribe_delete,
+                    ),
+                )
+                .route_layer(scope(Scope::WriteSubscriptions)),
+        );
+
+    let breakers = Router::new()
+        .route("/", on(MethodFilter::GET, apis::breakers::get_breakers))
+        .route_layer(scope(Scope::Read))
+        .merge(
+            Router::new()
+                .route(
+                    "/reset",
+                    on(
+                        MethodFilter::POST,
+                        apis::breakers::reset::post_breakers_reset,
+                    ),
+                )
+                .route_layer(scope(Scope::Admin)),
+        );
+
+    let inner = Router::new()
+        .route(
+            "/interval",
+            on(MethodFilter::GET, apis::inner::interval::get_interval)
+                .on(MethodFilter::POST, apis::inner::interval::post_interval),
+        )
+        .route(
+            "/config",
+            on(MethodFilter::GET, apis::inner::config::get_config)
+                .on(MethodFilter::PATCH, apis::inner::config::patch_config),
+        )
+        .route("/db", on(MethodFilter::GET, apis::inner::db::get_db_status))
+        .route(
+            "/backup",
+            on(MethodFilter::GET, apis::inner::backup::get_backup),
+        )
+        .route(
+            "/version",
+            on(
+                MethodFilter::GET,
+                han_utils::apis::inner::version::get_version,
+            ),
+        )
+        .route_layer(scope(Scope::Admin));
+
+    let app = Router::new()
+        .nest(
+            "/api",
+            Router::new()
                 .route("/info", on(MethodFilter::GET, apis::info::get_info))
+                .route(
+                    "/sources",
+                    on(MethodFilter::GET, apis::sources::get_sources),
+                )
+                // before the groups, they bring their own scopes
+                .route_layer(scope(Scope::Read))
+                .nest("/history", history)
+                .nest("/subscribe", subscribe)
+                .route(
+                    "/users",
+                    on(MethodFilter::GET, apis::users::get_users).route_layer(scope(Scope::Admin)),
+                )
+                // how sessions are made, open to everyone
                 .nest(
-                    "/inner",
+                    "/login",
                     Router::new()
-                        .route(
-                            "/interval",
-                            on(MethodFilter::GET, apis::inner::interval::get_interval)
-                                .on(MethodFilter::POST, apis::inner::interval::post_interval),
-                        )
-                        .route(
-                            "/version",
-                            on(
-                                MethodFilter::GET,
-                                han_utils::apis::inner::version::get_version,
-                            ),
-                        ),
-                ),
+                        .route("/code", post(apis::login::post_login_code))
+                        .route("/verify", post(apis::login::post_login_verify))
+                        .route("/logout", post(apis::login::post_logout)),
+                )
+                .nest("/breakers", breakers)
+                .nest("/inner", inner),
         )
+        // one pool for every handler
+        .layer(Extension(db))
         .layer(cors);
 
     Ok(app)